@@ -0,0 +1,297 @@
+//! 对比分片之前 Actor 单队列的peer管理器和分片的 LinkPeerManager
+//! 每轮由 TASKS 个任务并发执行 新建 -> 连接 -> 读取 -> 断线
+use anyhow::{bail, ensure, Result};
+use aqueue::Actor;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use ns_game::metrics::Metrics;
+use ns_game::peer::IPeer;
+use ns_game::services::{
+    ILinkPeerManager, ILinkPeerManagerPeer, LinkPeerManager, RandomTokenGenerator, TokenGenerator,
+};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// 每个任务的操作次数
+const OPS: usize = 64;
+/// 每次连接后读取peer的次数
+const READS: usize = 8;
+/// 生成token冲突时最多重试次数
+const CREATE_TOKEN_RETRY: usize = 16;
+
+struct BenchPeer {
+    token: u64,
+    account_id: i32,
+    proxy_id: AtomicUsize,
+    disconnect: AtomicBool,
+}
+
+impl Display for BenchPeer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.account_id, self.token)
+    }
+}
+
+#[async_trait::async_trait]
+impl IPeer for BenchPeer {
+    fn create(token: u64, account_id: i32) -> Self {
+        Self {
+            token,
+            account_id,
+            proxy_id: AtomicUsize::new(0),
+            disconnect: AtomicBool::new(true),
+        }
+    }
+
+    fn update(&self) {}
+
+    fn get_account_id(&self) -> i32 {
+        self.account_id
+    }
+
+    fn get_token(&self) -> u64 {
+        self.token
+    }
+
+    fn get_proxy_id(&self) -> usize {
+        self.proxy_id.load(Ordering::Acquire)
+    }
+
+    fn set_proxy_id(&self, proxy_id: usize) {
+        self.proxy_id.store(proxy_id, Ordering::Release)
+    }
+
+    fn set_disconnect(&self, disconnect: bool) {
+        self.disconnect.store(disconnect, Ordering::Release)
+    }
+
+    fn is_disconnect(&self) -> bool {
+        self.disconnect.load(Ordering::Acquire)
+    }
+
+    fn comparison_time(&self, _timestamp: i64) -> i64 {
+        0
+    }
+
+    async fn on_disconnect(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn on_clean(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// 分片之前的 LinkPeerManager 所有操作经过 Actor 队列
+/// 保留原实现的账号索引,代理索引,指标和token生成器
+struct LegacyPeerManager<T> {
+    peers: HashMap<u64, Arc<T>>,
+    account_index: HashMap<i32, Vec<u64>>,
+    proxy_index: HashMap<usize, HashSet<u64>>,
+    max_peers_per_account: usize,
+    metrics: Arc<Metrics>,
+    token_generator: Arc<dyn TokenGenerator>,
+}
+
+impl<T> Default for LegacyPeerManager<T> {
+    fn default() -> Self {
+        Self {
+            peers: Default::default(),
+            account_index: Default::default(),
+            proxy_index: Default::default(),
+            max_peers_per_account: 0,
+            metrics: Default::default(),
+            token_generator: Arc::new(RandomTokenGenerator),
+        }
+    }
+}
+
+impl<T: IPeer + 'static> LegacyPeerManager<T> {
+    fn insert_peer(&mut self, peer: Arc<T>) {
+        let token = peer.get_token();
+        self.account_index
+            .entry(peer.get_account_id())
+            .or_default()
+            .push(token);
+        self.proxy_index
+            .entry(peer.get_proxy_id())
+            .or_default()
+            .insert(token);
+        self.peers.insert(token, peer);
+    }
+
+    fn remove_proxy_index(&mut self, proxy_id: usize, token: u64) {
+        if let Some(tokens) = self.proxy_index.get_mut(&proxy_id) {
+            tokens.remove(&token);
+            if tokens.is_empty() {
+                self.proxy_index.remove(&proxy_id);
+            }
+        }
+    }
+
+    fn create_peer(&mut self, account_id: i32) -> Result<u64> {
+        let count = self.account_index.get(&account_id).map_or(0, Vec::len);
+        if self.max_peers_per_account > 0 && count >= self.max_peers_per_account {
+            bail!("account id:{account_id} peers count:{count}");
+        }
+        let mut retry = 0;
+        let token = loop {
+            let token = self.token_generator.generate(account_id)?;
+            if token != 0 && !self.peers.contains_key(&token) {
+                break token;
+            }
+            retry += 1;
+            ensure!(retry < CREATE_TOKEN_RETRY, "create token fail");
+        };
+        self.insert_peer(Arc::new(T::create(token, account_id)));
+        self.metrics.peers_created.inc();
+        Ok(token)
+    }
+
+    fn peer_connect(&mut self, proxy_id: usize, account_id: i32, token: u64) -> Result<()> {
+        self.token_generator.verify(account_id, token)?;
+        match self.peers.get(&token).cloned() {
+            Some(peer) if peer.get_account_id() == account_id => {
+                let old_proxy_id = peer.get_proxy_id();
+                if old_proxy_id != proxy_id {
+                    self.remove_proxy_index(old_proxy_id, token);
+                    self.proxy_index.entry(proxy_id).or_default().insert(token);
+                }
+                peer.set_proxy_id(proxy_id);
+                peer.set_disconnect(false);
+                Ok(())
+            }
+            _ => bail!("token:{token} not found"),
+        }
+    }
+
+    fn disconnect(&mut self, token: u64) {
+        if let Some(peer) = self.peers.get(&token).cloned() {
+            peer.set_disconnect(true);
+            tokio::spawn(async move { peer.on_disconnect().await });
+        }
+    }
+}
+
+#[async_trait::async_trait]
+trait ILegacyPeerManager {
+    async fn create_peer(&self, account_id: i32) -> Result<u64>;
+    async fn connect_token(&self, proxy_id: usize, account_id: i32, token: u64) -> Result<()>;
+    fn get_peer(&self, token: u64) -> Option<Arc<BenchPeer>>;
+    async fn disconnect_token(&self, token: u64);
+}
+
+#[async_trait::async_trait]
+impl ILegacyPeerManager for Actor<LegacyPeerManager<BenchPeer>> {
+    async fn create_peer(&self, account_id: i32) -> Result<u64> {
+        self.inner_call(|inner| async move { inner.get_mut().create_peer(account_id) })
+            .await
+    }
+
+    async fn connect_token(&self, proxy_id: usize, account_id: i32, token: u64) -> Result<()> {
+        self.inner_call(
+            |inner| async move { inner.get_mut().peer_connect(proxy_id, account_id, token) },
+        )
+        .await
+    }
+
+    /// 和原实现一样不经过队列直接读取
+    fn get_peer(&self, token: u64) -> Option<Arc<BenchPeer>> {
+        unsafe { self.deref_inner().peers.get(&token).cloned() }
+    }
+
+    async fn disconnect_token(&self, token: u64) {
+        self.inner_call(|inner| async move { inner.get_mut().disconnect(token) })
+            .await
+    }
+}
+
+static ACCOUNT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[inline]
+fn next_account_id() -> i32 {
+    ACCOUNT_ID.fetch_add(1, Ordering::Relaxed) as i32
+}
+
+async fn run_actor(
+    peers: Arc<Actor<LegacyPeerManager<BenchPeer>>>,
+    tasks: usize,
+) -> Arc<Actor<LegacyPeerManager<BenchPeer>>> {
+    let handles = (0..tasks)
+        .map(|i| {
+            let peers = peers.clone();
+            tokio::spawn(async move {
+                for _ in 0..OPS {
+                    let account_id = next_account_id();
+                    let token = peers.create_peer(account_id).await.unwrap();
+                    peers.connect_token(i + 1, account_id, token).await.unwrap();
+                    for _ in 0..READS {
+                        assert!(peers.get_peer(token).is_some());
+                    }
+                    peers.disconnect_token(token).await;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    peers
+}
+
+async fn run_sharded(
+    peers: Arc<LinkPeerManager<BenchPeer>>,
+    tasks: usize,
+) -> Arc<LinkPeerManager<BenchPeer>> {
+    let handles = (0..tasks)
+        .map(|i| {
+            let peers = peers.clone();
+            tokio::spawn(async move {
+                for _ in 0..OPS {
+                    let account_id = next_account_id();
+                    let token = peers.create_peer(account_id).await.unwrap();
+                    peers.connect_token(i + 1, account_id, token).await.unwrap();
+                    for _ in 0..READS {
+                        assert!(peers.get_peer(token).is_some());
+                    }
+                    peers.disconnect_token(i + 1, token).await;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.await.unwrap();
+    }
+    peers
+}
+
+fn peer_manager(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("peer_manager");
+    // 每轮使用新的管理器 释放放在计时之外
+    for tasks in [1, 8, 64] {
+        group.bench_with_input(BenchmarkId::new("actor", tasks), &tasks, |b, &tasks| {
+            b.to_async(&runtime).iter_batched(
+                || Arc::new(Actor::new(LegacyPeerManager::default())),
+                |peers| run_actor(peers, tasks),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("sharded", tasks), &tasks, |b, &tasks| {
+            b.to_async(&runtime).iter_batched(
+                || Arc::new(LinkPeerManager::default()),
+                |peers| run_sharded(peers, tasks),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, peer_manager);
+criterion_main!(benches);
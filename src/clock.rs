@@ -0,0 +1,174 @@
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// 时钟
+/// time 模块,peer管理器和定时器都从这里取当前时间
+pub trait Clock: Send + Sync {
+    /// 当前UTC时间
+    fn now(&self) -> DateTime<Utc>;
+    /// 等待一段时间
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// 系统时钟
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[inline]
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    #[inline]
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// 手动时钟 用于测试
+/// 时间只在 advance 或 set 时变化,sleep 在时间推进到目标后返回
+/// ``` ignore
+/// let clock = Arc::new(ManualClock::default());
+/// set_clock(clock.clone());
+/// clock.advance(Duration::from_secs(300));
+/// ```
+pub struct ManualClock {
+    now: watch::Sender<DateTime<Utc>>,
+}
+
+impl Default for ManualClock {
+    #[inline]
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl ManualClock {
+    #[inline]
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: watch::Sender::new(now),
+        }
+    }
+
+    /// 时间前进
+    #[inline]
+    pub fn advance(&self, duration: Duration) {
+        let duration = chrono::Duration::from_std(duration).unwrap_or(chrono::Duration::MAX);
+        self.now
+            .send_modify(|now| *now = now.checked_add_signed(duration).unwrap_or(*now));
+    }
+
+    /// 设置当前时间
+    #[inline]
+    pub fn set(&self, now: DateTime<Utc>) {
+        self.now.send_replace(now);
+    }
+}
+
+impl Clock for ManualClock {
+    #[inline]
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.borrow()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let mut now = self.now.subscribe();
+        let deadline = chrono::Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| now.borrow().checked_add_signed(duration));
+        Box::pin(async move {
+            let Some(deadline) = deadline else {
+                return std::future::pending().await;
+            };
+            while *now.borrow_and_update() < deadline {
+                // 时钟已释放 不会再前进
+                if now.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        })
+    }
+}
+
+/// 是否设置了自定义时钟,未设置时直接使用系统时间
+static CUSTOM: AtomicBool = AtomicBool::new(false);
+
+static CLOCK: Lazy<RwLock<Arc<dyn Clock>>> = Lazy::new(|| RwLock::new(Arc::new(SystemClock)));
+
+/// 设置全局时钟
+#[inline]
+pub fn set_clock(clock: Arc<dyn Clock>) {
+    *CLOCK.write().unwrap_or_else(PoisonError::into_inner) = clock;
+    CUSTOM.store(true, Ordering::Release);
+}
+
+/// 恢复为系统时钟
+#[inline]
+pub fn reset_clock() {
+    CUSTOM.store(false, Ordering::Release);
+    *CLOCK.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(SystemClock);
+}
+
+/// 当前全局时钟
+#[inline]
+pub fn clock() -> Arc<dyn Clock> {
+    CLOCK.read().unwrap_or_else(PoisonError::into_inner).clone()
+}
+
+/// 当前UTC时间
+#[inline]
+pub fn now() -> DateTime<Utc> {
+    if CUSTOM.load(Ordering::Acquire) {
+        clock().now()
+    } else {
+        Utc::now()
+    }
+}
+
+/// 按全局时钟等待
+#[inline]
+pub async fn sleep(duration: Duration) {
+    if CUSTOM.load(Ordering::Acquire) {
+        clock().sleep(duration).await
+    } else {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// 测试用手动时钟
+/// 全局时钟的测试需要串行,持有期间设置为手动时钟,释放后恢复系统时钟
+#[cfg(test)]
+pub(crate) struct TestClock {
+    clock: Arc<ManualClock>,
+    _lock: tokio::sync::MutexGuard<'static, ()>,
+}
+
+#[cfg(test)]
+impl TestClock {
+    pub(crate) async fn set() -> Self {
+        static LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+        let lock = LOCK.lock().await;
+        let clock = Arc::new(ManualClock::default());
+        set_clock(clock.clone());
+        Self { clock, _lock: lock }
+    }
+
+    /// 时间前进
+    pub(crate) fn advance(&self, duration: Duration) {
+        self.clock.advance(duration);
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestClock {
+    fn drop(&mut self) {
+        reset_clock();
+    }
+}
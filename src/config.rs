@@ -1,5 +1,20 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::static_def::find_file;
+
+/// 默认配置文件名
+pub const CONFIG_FILE_NAME: &str = "base_config.toml";
+
+/// 指定配置文件路径的环境变量
+pub const CONFIG_PATH_ENV: &str = "NS_GAME_CONFIG";
+
+/// 配置覆盖环境变量前缀
+/// 变量名为 前缀+toml键路径大写 例如 master.addr => NS_GAME_MASTER_ADDR
+pub const CONFIG_ENV_PREFIX: &str = "NS_GAME_";
 
 /// 大厅配置
 #[derive(Debug, Deserialize, Clone)]
@@ -10,6 +25,21 @@ pub struct Config {
     pub proxy_listen: netxserver::prelude::ServerOption,
     /// 主服务连接配置
     pub master: netxclient::prelude::ServerOption,
+    /// 主服务连接策略
+    #[serde(default)]
+    pub master_connect: MasterConnectConfig,
+    /// 健康检查http服务 不配置则不启动
+    #[serde(default)]
+    pub health: Option<HealthConfig>,
+    /// 指标http服务 不配置则不启动
+    #[serde(default)]
+    pub metrics: Option<MetricsConfig>,
+    /// token 签名 不配置则使用随机token
+    #[serde(default)]
+    pub token: Option<TokenConfig>,
+    /// 配置文件路径 重新加载时使用
+    #[serde(skip)]
+    source: Option<PathBuf>,
 }
 
 impl Config {
@@ -17,6 +47,379 @@ impl Config {
     pub fn load_config(content: &str) -> Result<Self> {
         Ok(toml::from_str(content)?)
     }
+
+    /// 从指定路径加载,并应用环境变量覆盖
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("read config file:{} error", path.display()))?;
+        let mut config = Self::load_config(&content)
+            .with_context(|| format!("parse config file:{} error", path.display()))?;
+        config.apply_env()?;
+        config
+            .validate()
+            .with_context(|| format!("config file:{} invalid", path.display()))?;
+        config.source = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// 配置文件路径 不是从文件加载时为None
+    #[inline]
+    pub fn source(&self) -> Option<&Path> {
+        self.source.as_deref()
+    }
+
+    /// 加载默认配置
+    /// 优先使用 NS_GAME_CONFIG 指定的路径,否则依次查找 exe目录,当前目录,当前目录的上级目录下的 base_config.toml
+    #[inline]
+    pub fn load_default() -> Result<Self> {
+        Self::load(Self::default_path()?)
+    }
+
+    /// 默认配置路径
+    #[inline]
+    pub fn default_path() -> Result<PathBuf> {
+        match std::env::var(CONFIG_PATH_ENV) {
+            Ok(path) => Ok(PathBuf::from(path)),
+            Err(_) => find_file(CONFIG_FILE_NAME),
+        }
+    }
+
+    /// 合并新配置中可在线修改的部分
+    /// 返回合并后的配置 以及新配置中不能在线修改而被忽略的键
+    pub fn merge_reloadable(&self, new: &Config) -> (Config, Vec<&'static str>) {
+        let mut ignored = Vec::new();
+        if self.base.server_id != new.base.server_id {
+            ignored.push("base.server_id");
+        }
+        if self.proxy_listen.addr != new.proxy_listen.addr {
+            ignored.push("proxy_listen.addr");
+        }
+        if self.proxy_listen.service_name != new.proxy_listen.service_name {
+            ignored.push("proxy_listen.service_name");
+        }
+        if self.proxy_listen.verify_key != new.proxy_listen.verify_key {
+            ignored.push("proxy_listen.verify_key");
+        }
+        if self.proxy_listen.request_out_time != new.proxy_listen.request_out_time {
+            ignored.push("proxy_listen.request_out_time");
+        }
+        if self.proxy_listen.session_save_time != new.proxy_listen.session_save_time {
+            ignored.push("proxy_listen.session_save_time");
+        }
+        if self.master.addr != new.master.addr {
+            ignored.push("master.addr");
+        }
+        if self.master.service_name != new.master.service_name {
+            ignored.push("master.service_name");
+        }
+        if self.master.verify_key != new.master.verify_key {
+            ignored.push("master.verify_key");
+        }
+        if self.master.request_out_time_ms != new.master.request_out_time_ms {
+            ignored.push("master.request_out_time_ms");
+        }
+        if self.master_connect != new.master_connect {
+            ignored.push("master_connect");
+        }
+        if self.health != new.health {
+            ignored.push("health");
+        }
+        if self.metrics != new.metrics {
+            ignored.push("metrics");
+        }
+        if self.token != new.token {
+            ignored.push("token");
+        }
+
+        let mut config = self.clone();
+        config.base = BaseConfig {
+            server_id: self.base.server_id,
+            ..new.base.clone()
+        };
+        (config, ignored)
+    }
+
+    /// 使用环境变量覆盖配置
+    pub fn apply_env(&mut self) -> Result<()> {
+        env_override("base.server_id", &mut self.base.server_id)?;
+        env_override("base.strict", &mut self.base.strict)?;
+        env_override(
+            "base.shutdown_timeout_sec",
+            &mut self.base.shutdown_timeout_sec,
+        )?;
+        env_override("base.housekeeping_timer", &mut self.base.housekeeping_timer)?;
+        env_override(
+            "base.keep_alive_interval_sec",
+            &mut self.base.keep_alive_interval_sec,
+        )?;
+        env_override(
+            "base.max_peers_per_account",
+            &mut self.base.max_peers_per_account,
+        )?;
+        env_override(
+            "base.session_limit_policy",
+            &mut self.base.session_limit_policy,
+        )?;
+        env_override(
+            "base.allow_connect_takeover",
+            &mut self.base.allow_connect_takeover,
+        )?;
+        env_override(
+            "base.peer_clean_timeout_sec",
+            &mut self.base.peer_clean_timeout_sec,
+        )?;
+        env_override(
+            "base.pending_connect_timeout_sec",
+            &mut self.base.pending_connect_timeout_sec,
+        )?;
+        env_override(
+            "base.account_cache_cleans_timeout_sec",
+            &mut self.base.account_cache_cleans_timeout_sec,
+        )?;
+
+        env_override("master.addr", &mut self.master.addr)?;
+        env_override("master.service_name", &mut self.master.service_name)?;
+        env_override("master.verify_key", &mut self.master.verify_key)?;
+        env_override(
+            "master.request_out_time_ms",
+            &mut self.master.request_out_time_ms,
+        )?;
+
+        env_override(
+            "master_connect.startup_policy",
+            &mut self.master_connect.startup_policy,
+        )?;
+        env_override(
+            "master_connect.retry_max",
+            &mut self.master_connect.retry_max,
+        )?;
+        env_override(
+            "master_connect.retry_initial_ms",
+            &mut self.master_connect.retry_initial_ms,
+        )?;
+        env_override(
+            "master_connect.retry_max_ms",
+            &mut self.master_connect.retry_max_ms,
+        )?;
+        env_override(
+            "master_connect.reconnect",
+            &mut self.master_connect.reconnect,
+        )?;
+
+        if let Ok(addr) = std::env::var(env_key("health.addr")) {
+            log::info!("config health.addr override by env");
+            self.health = Some(HealthConfig { addr });
+        }
+        if let Ok(addr) = std::env::var(env_key("metrics.addr")) {
+            log::info!("config metrics.addr override by env");
+            self.metrics = Some(MetricsConfig { addr });
+        }
+        if let Ok(secret) = std::env::var(env_key("token.secret")) {
+            log::info!("config token.secret override by env");
+            match self.token {
+                Some(ref mut token) => token.secret = secret,
+                None => {
+                    self.token = Some(TokenConfig {
+                        secret,
+                        expire_sec: default_token_expire_sec(),
+                    })
+                }
+            }
+        }
+        if let Some(ref mut token) = self.token {
+            env_override("token.expire_sec", &mut token.expire_sec)?;
+        }
+
+        env_override("proxy_listen.addr", &mut self.proxy_listen.addr)?;
+        env_override(
+            "proxy_listen.service_name",
+            &mut self.proxy_listen.service_name,
+        )?;
+        env_override("proxy_listen.verify_key", &mut self.proxy_listen.verify_key)?;
+        env_override(
+            "proxy_listen.request_out_time",
+            &mut self.proxy_listen.request_out_time,
+        )?;
+        env_override(
+            "proxy_listen.session_save_time",
+            &mut self.proxy_listen.session_save_time,
+        )?;
+        Ok(())
+    }
+
+    /// 检查配置,返回所有错误和警告 格式为 "toml键路径: 问题"
+    /// strict 模式下空的 verify_key 视为错误,否则为警告
+    pub fn check(&self) -> ConfigCheck {
+        let mut problems = Vec::new();
+        let mut warnings = Vec::new();
+        let mut problem = |key: &str, msg: String| problems.push(format!("{key}: {msg}"));
+
+        if self.base.server_id == 0 {
+            problem("base.server_id", "must not be 0".into());
+        }
+        if self.base.peer_clean_timeout_sec <= 0 {
+            problem(
+                "base.peer_clean_timeout_sec",
+                format!("must be > 0, got {}", self.base.peer_clean_timeout_sec),
+            );
+        }
+        if self.base.pending_connect_timeout_sec <= 0 {
+            problem(
+                "base.pending_connect_timeout_sec",
+                format!("must be > 0, got {}", self.base.pending_connect_timeout_sec),
+            );
+        }
+        if self.base.account_cache_cleans_timeout_sec <= 0 {
+            problem(
+                "base.account_cache_cleans_timeout_sec",
+                format!(
+                    "must be > 0, got {}",
+                    self.base.account_cache_cleans_timeout_sec
+                ),
+            );
+        }
+        if self.base.housekeeping_timer && self.base.keep_alive_interval_sec == 0 {
+            problem("base.keep_alive_interval_sec", "must be > 0".into());
+        }
+
+        if let Err(err) = self.proxy_listen.addr.parse::<SocketAddr>() {
+            problem(
+                "proxy_listen.addr",
+                format!("'{}' is not ip:port, {err}", self.proxy_listen.addr),
+            );
+        }
+        if self.proxy_listen.service_name.is_empty() {
+            problem("proxy_listen.service_name", "must not be empty".into());
+        }
+        if self.proxy_listen.request_out_time == 0 {
+            problem("proxy_listen.request_out_time", "must be > 0".into());
+        }
+
+        match self.master.addr.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+            _ => problem(
+                "master.addr",
+                format!("'{}' is not host:port", self.master.addr),
+            ),
+        }
+        if self.master.service_name.is_empty() {
+            problem("master.service_name", "must not be empty".into());
+        }
+        if self.master.request_out_time_ms == 0 {
+            problem("master.request_out_time_ms", "must be > 0".into());
+        }
+
+        if self.master_connect.retry_initial_ms == 0 {
+            problem("master_connect.retry_initial_ms", "must be > 0".into());
+        }
+        if self.master_connect.retry_max_ms < self.master_connect.retry_initial_ms {
+            problem(
+                "master_connect.retry_max_ms",
+                format!(
+                    "must be >= retry_initial_ms {}",
+                    self.master_connect.retry_initial_ms
+                ),
+            );
+        }
+
+        if let Some(ref health) = self.health {
+            if let Err(err) = health.addr.parse::<SocketAddr>() {
+                problem(
+                    "health.addr",
+                    format!("'{}' is not ip:port, {err}", health.addr),
+                );
+            }
+        }
+
+        if let Some(ref metrics) = self.metrics {
+            if let Err(err) = metrics.addr.parse::<SocketAddr>() {
+                problem(
+                    "metrics.addr",
+                    format!("'{}' is not ip:port, {err}", metrics.addr),
+                );
+            }
+        }
+
+        if let Some(ref token) = self.token {
+            if token.secret.is_empty() {
+                problem("token.secret", "must not be empty".into());
+            }
+            if token.expire_sec == 0 || token.expire_sec > TOKEN_MAX_EXPIRE_SEC {
+                problem(
+                    "token.expire_sec",
+                    format!(
+                        "must be in 1..={TOKEN_MAX_EXPIRE_SEC}, got {}",
+                        token.expire_sec
+                    ),
+                );
+            }
+        }
+
+        for (key, verify_key) in [
+            ("master.verify_key", &self.master.verify_key),
+            ("proxy_listen.verify_key", &self.proxy_listen.verify_key),
+        ] {
+            if verify_key.is_empty() {
+                if self.base.strict {
+                    problem(key, "must not be empty in strict mode".into());
+                } else {
+                    warnings.push(format!("{key}: is empty"));
+                }
+            }
+        }
+
+        ConfigCheck {
+            errors: problems,
+            warnings,
+        }
+    }
+
+    /// 检查配置,有错误时返回包含所有错误的错误 警告不影响结果
+    #[inline]
+    pub fn validate(&self) -> Result<()> {
+        let errors = self.check().errors;
+        if !errors.is_empty() {
+            bail!("config error:\n  {}", errors.join("\n  "))
+        }
+        Ok(())
+    }
+}
+
+/// 配置检查结果 格式为 "toml键路径: 问题"
+#[derive(Debug, Clone, Default)]
+pub struct ConfigCheck {
+    /// 错误 有错误时配置不能使用
+    pub errors: Vec<String>,
+    /// 警告
+    pub warnings: Vec<String>,
+}
+
+/// 配置键路径对应的环境变量名
+#[inline]
+pub fn env_key(key_path: &str) -> String {
+    format!(
+        "{CONFIG_ENV_PREFIX}{}",
+        key_path.replace('.', "_").to_uppercase()
+    )
+}
+
+/// 如果设置了环境变量则覆盖此值
+#[inline]
+fn env_override<T>(key_path: &str, value: &mut T) -> Result<()>
+where
+    T: FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    let env_key = env_key(key_path);
+    if let Ok(env_value) = std::env::var(&env_key) {
+        *value = env_value
+            .parse()
+            .map_err(Into::into)
+            .with_context(|| format!("env {env_key} for {key_path} error:{env_value}"))?;
+        log::info!("config {key_path} override by env {env_key}");
+    }
+    Ok(())
 }
 
 /// 基本设置
@@ -26,6 +429,316 @@ pub struct BaseConfig {
     pub server_id: u32,
     /// 服务器 PEER 清理时间
     pub peer_clean_timeout_sec: i64,
+    /// 新建token多久没有连接清理(秒)
+    #[serde(default = "default_pending_connect_timeout_sec")]
+    pub pending_connect_timeout_sec: i64,
     /// 缓存的account信息 多久没访问清理(秒)
     pub account_cache_cleans_timeout_sec: i64,
+    /// 严格模式 不允许空的 verify_key
+    #[serde(default)]
+    pub strict: bool,
+    /// 关闭服务时等待请求完成和清理peer的最长时间(秒)
+    #[serde(default = "default_shutdown_timeout_sec")]
+    pub shutdown_timeout_sec: u64,
+    /// 是否由 Game 启动内置定时器(清理peer,通知master账号保活)
+    #[serde(default = "default_true")]
+    pub housekeeping_timer: bool,
+    /// 通知master账号保活间隔(秒)
+    #[serde(default = "default_keep_alive_interval_sec")]
+    pub keep_alive_interval_sec: u64,
+    /// 每个账号最多peer数量 0不限制
+    #[serde(default)]
+    pub max_peers_per_account: usize,
+    /// 超过账号peer数量时的处理策略
+    #[serde(default)]
+    pub session_limit_policy: SessionLimitPolicy,
+    /// 已连接的token是否允许从其他代理连接 允许时踢掉原连接
+    #[serde(default)]
+    pub allow_connect_takeover: bool,
+}
+
+/// 超过账号peer数量时的处理策略
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionLimitPolicy {
+    /// 拒绝新建token
+    Reject,
+    /// 踢掉最早创建的peer
+    #[default]
+    EvictOldest,
+}
+
+impl FromStr for SessionLimitPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "reject" => Ok(Self::Reject),
+            "evict_oldest" => Ok(Self::EvictOldest),
+            _ => bail!("unknown session limit policy:{s}, use reject evict_oldest"),
+        }
+    }
+}
+
+#[inline]
+fn default_true() -> bool {
+    true
+}
+
+#[inline]
+fn default_keep_alive_interval_sec() -> u64 {
+    60
+}
+
+#[inline]
+fn default_pending_connect_timeout_sec() -> i64 {
+    60
+}
+
+#[inline]
+fn default_shutdown_timeout_sec() -> u64 {
+    30
+}
+
+/// 启动时连接主服务失败的处理策略
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MasterStartupPolicy {
+    /// 连接失败立即返回错误
+    FailFast,
+    /// 指数退避重试 retry_max 次,仍失败返回错误
+    Retry,
+    /// 只打印错误继续启动,由后台重连
+    #[default]
+    Background,
+}
+
+impl FromStr for MasterStartupPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "fail_fast" => Ok(Self::FailFast),
+            "retry" => Ok(Self::Retry),
+            "background" => Ok(Self::Background),
+            _ => bail!("unknown master startup policy:{s}, use fail_fast retry background"),
+        }
+    }
+}
+
+/// 主服务连接策略
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct MasterConnectConfig {
+    /// 启动时连接失败的处理策略
+    pub startup_policy: MasterStartupPolicy,
+    /// 启动时最多重试次数
+    pub retry_max: u32,
+    /// 重试初始间隔(毫秒),每次翻倍
+    pub retry_initial_ms: u64,
+    /// 重试最大间隔(毫秒)
+    pub retry_max_ms: u64,
+    /// 断线后是否后台重连
+    pub reconnect: bool,
+}
+
+impl Default for MasterConnectConfig {
+    fn default() -> Self {
+        Self {
+            startup_policy: MasterStartupPolicy::Background,
+            retry_max: 5,
+            retry_initial_ms: 500,
+            retry_max_ms: 30000,
+            reconnect: true,
+        }
+    }
+}
+
+impl MasterConnectConfig {
+    /// 第 attempt 次重试的等待时间
+    #[inline]
+    pub fn backoff_ms(&self, attempt: u32) -> u64 {
+        self.retry_initial_ms
+            .saturating_mul(1u64 << attempt.min(32))
+            .min(self.retry_max_ms)
+    }
+}
+
+/// 健康检查http服务配置
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct HealthConfig {
+    /// 监听地址
+    pub addr: String,
+}
+
+/// 指标http服务配置
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MetricsConfig {
+    /// 监听地址
+    pub addr: String,
+}
+
+/// token 最长有效期(秒) 约半年
+const TOKEN_MAX_EXPIRE_SEC: u64 = 180 * 24 * 3600;
+
+/// token 签名配置
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TokenConfig {
+    /// 签名密钥
+    pub secret: String,
+    /// 有效期(秒),新建的token过期前没有连接过则不能连接 已连接过的peer断线重连不检查过期
+    #[serde(default = "default_token_expire_sec")]
+    pub expire_sec: u64,
+}
+
+#[inline]
+fn default_token_expire_sec() -> u64 {
+    86400
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::load_config(include_str!("../base_config.toml")).unwrap()
+    }
+
+    #[test]
+    fn check_reports_every_error() {
+        let mut config = config();
+        assert!(config.check().errors.is_empty());
+        config.base.server_id = 0;
+        config.base.peer_clean_timeout_sec = 0;
+        config.proxy_listen.addr = "bad".into();
+        config.master.addr = ":7450".into();
+        config.token = Some(TokenConfig {
+            secret: String::new(),
+            expire_sec: 0,
+        });
+
+        let errors = config.check().errors;
+        let keys = errors
+            .iter()
+            .map(|err| err.split_once(':').unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            [
+                "base.server_id",
+                "base.peer_clean_timeout_sec",
+                "proxy_listen.addr",
+                "master.addr",
+                "token.secret",
+                "token.expire_sec",
+            ]
+        );
+        let err = config.validate().unwrap_err().to_string();
+        for error in errors {
+            assert!(err.contains(&error), "{err}");
+        }
+    }
+
+    #[test]
+    fn strict_verify_key() {
+        let mut config = config();
+        let check = config.check();
+        assert!(check.errors.is_empty());
+        assert_eq!(
+            check.warnings,
+            [
+                "master.verify_key: is empty",
+                "proxy_listen.verify_key: is empty"
+            ]
+        );
+        config.validate().unwrap();
+
+        config.base.strict = true;
+        let check = config.check();
+        assert!(check.warnings.is_empty());
+        assert_eq!(
+            check.errors,
+            [
+                "master.verify_key: must not be empty in strict mode",
+                "proxy_listen.verify_key: must not be empty in strict mode"
+            ]
+        );
+        assert!(config.validate().is_err());
+
+        config.master.verify_key = "key".into();
+        config.proxy_listen.verify_key = "key".into();
+        let check = config.check();
+        assert!(check.errors.is_empty() && check.warnings.is_empty());
+    }
+
+    #[test]
+    fn env_override_parse() {
+        assert_eq!(
+            env_key("master_connect.retry_max"),
+            "NS_GAME_MASTER_CONNECT_RETRY_MAX"
+        );
+
+        let mut value = 1u32;
+        env_override("test.env_number", &mut value).unwrap();
+        assert_eq!(value, 1);
+        std::env::set_var("NS_GAME_TEST_ENV_NUMBER", "12");
+        env_override("test.env_number", &mut value).unwrap();
+        assert_eq!(value, 12);
+        std::env::set_var("NS_GAME_TEST_ENV_NUMBER", "abc");
+        let err = format!(
+            "{:#}",
+            env_override("test.env_number", &mut value).unwrap_err()
+        );
+        assert!(err.contains("NS_GAME_TEST_ENV_NUMBER"), "{err}");
+        assert!(err.contains("test.env_number"), "{err}");
+        assert_eq!(value, 12);
+        std::env::remove_var("NS_GAME_TEST_ENV_NUMBER");
+
+        let mut policy = SessionLimitPolicy::EvictOldest;
+        std::env::set_var("NS_GAME_TEST_ENV_POLICY", "reject");
+        env_override("test.env_policy", &mut policy).unwrap();
+        assert_eq!(policy, SessionLimitPolicy::Reject);
+        std::env::set_var("NS_GAME_TEST_ENV_POLICY", "drop");
+        let err = format!(
+            "{:#}",
+            env_override("test.env_policy", &mut policy).unwrap_err()
+        );
+        assert!(err.contains("unknown session limit policy:drop"), "{err}");
+        std::env::remove_var("NS_GAME_TEST_ENV_POLICY");
+    }
+
+    #[test]
+    fn merge_reloadable_keeps_server_id() {
+        let old = config();
+        let mut new = config();
+        let (merged, ignored) = old.merge_reloadable(&new);
+        assert!(ignored.is_empty());
+        assert_eq!(merged.base.server_id, old.base.server_id);
+
+        new.base.server_id = old.base.server_id + 1;
+        new.base.peer_clean_timeout_sec = 10;
+        new.base.max_peers_per_account = 3;
+        new.master.addr = "127.0.0.1:7451".into();
+        new.proxy_listen.verify_key = "key".into();
+        new.health = Some(HealthConfig {
+            addr: "127.0.0.1:10252".into(),
+        });
+
+        let (merged, ignored) = old.merge_reloadable(&new);
+        assert_eq!(
+            ignored,
+            [
+                "base.server_id",
+                "proxy_listen.verify_key",
+                "master.addr",
+                "health"
+            ]
+        );
+        assert_eq!(merged.base.server_id, old.base.server_id);
+        assert_eq!(merged.base.peer_clean_timeout_sec, 10);
+        assert_eq!(merged.base.max_peers_per_account, 3);
+        assert_eq!(merged.master.addr, old.master.addr);
+        assert_eq!(merged.proxy_listen.verify_key, old.proxy_listen.verify_key);
+        assert!(merged.health.is_none());
+    }
 }
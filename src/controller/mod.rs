@@ -1,16 +1,19 @@
 mod proxy;
 mod proxy_interface;
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use netxserver::prelude::*;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use crate::Game;
+
 pub use proxy::*;
 pub use proxy_interface::*;
 
 /// 代理控制器
 pub struct ProxyController {
+    pub game: Arc<Game>,
     pub token: NetxToken<Self>,
     pub proxy_id: AtomicUsize,
 }
@@ -26,7 +29,15 @@ impl Drop for ProxyController {
     }
 }
 
-pub struct ImplCreateProxyController;
+pub struct ImplCreateProxyController {
+    game: Arc<Game>,
+}
+
+impl ImplCreateProxyController {
+    pub fn new(game: Arc<Game>) -> Self {
+        Self { game }
+    }
+}
 
 impl ICreateController for ImplCreateProxyController {
     type Controller = ProxyController;
@@ -36,7 +47,10 @@ impl ICreateController for ImplCreateProxyController {
         &self,
         token: NetxToken<Self::Controller>,
     ) -> Result<Arc<Self::Controller>> {
+        // 关闭时拒绝新的代理连接
+        ensure!(!self.game.is_shutdown(), "game is shutdown");
         Ok(Arc::new(ProxyController {
+            game: self.game.clone(),
             token,
             proxy_id: Default::default(),
         }))
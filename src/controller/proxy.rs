@@ -1,12 +1,12 @@
 use crate::controller::ProxyController;
+use crate::packers::error::format_gen_error;
 use crate::packers::GetTokenResult;
 use crate::services::IProxyService;
-use crate::static_def::PROXY;
-use crate::GAME;
-use anyhow::{ensure, Context, Result};
+use anyhow::{ensure, Result};
 use netxserver::prelude::tcpserver::IPeer;
 use netxserver::prelude::*;
 use std::sync::atomic::Ordering;
+use std::time::Instant;
 
 #[build(ProxyController)]
 pub trait IProxyController {
@@ -65,12 +65,11 @@ impl IProxyController for ProxyController {
                 )
             }
         }
-        PROXY.remove(proxy_id).await;
-        GAME.get()
-            .context("not install game")?
-            .peers
-            .disconnect_for_proxy(proxy_id)
-            .await;
+        if proxy_id != 0 {
+            self.game.metrics.proxies_disconnected.inc();
+        }
+        self.game.proxy.remove(proxy_id).await;
+        self.game.peers.disconnect_for_proxy(proxy_id).await;
         Ok(())
     }
 
@@ -80,26 +79,26 @@ impl IProxyController for ProxyController {
         ensure!(proxy_id != 0, "proxy not 0");
         log::info!("register proxy id:{proxy_id}");
         self.proxy_id.store(proxy_id, Ordering::Release);
-        PROXY.add(proxy_id, self.token.get_session_id()).await;
+        self.game.metrics.proxies_registered.inc();
+        self.game
+            .proxy
+            .add(proxy_id, self.token.get_session_id())
+            .await;
         Ok(())
     }
 
     /// 新建peer token
     #[inline]
     async fn create_token(&self, account_id: i32) -> Result<u64> {
-        GAME.get()
-            .context("not install game")?
-            .peers
-            .create_peer(account_id)
-            .await
+        ensure!(!self.game.is_shutdown(), "game is shutdown");
+        self.game.peers.create_peer(account_id).await
     }
 
     /// 长连接携带token链接
     #[inline]
     async fn connect_token(&self, account_id: i32, token: u64) -> Result<()> {
         let proxy_id = self.proxy_id.load(Ordering::Acquire);
-        GAME.get()
-            .context("not install game")?
+        self.game
             .peers
             .connect_token(proxy_id, account_id, token)
             .await
@@ -108,27 +107,36 @@ impl IProxyController for ProxyController {
     /// peer 断线
     #[inline]
     async fn disconnect_token(&self, token: u64) {
-        GAME.get()
-            .context("not install game")
-            .unwrap()
-            .peers
-            .disconnect_token(token)
-            .await;
+        let proxy_id = self.proxy_id.load(Ordering::Acquire);
+        self.game.peers.disconnect_token(proxy_id, token).await;
     }
 
     /// 功能调用
     #[inline]
     async fn func(&self, account_id: i32, token: u64, data: Vec<u8>) -> Result<Vec<u8>> {
-        (GAME.get().context("not found game install")?.func)(self, account_id, token, data).await
+        let Some(_guard) = self.game.begin_request() else {
+            return format_gen_error(None, 0, "server is shutdown".into());
+        };
+        let start = Instant::now();
+        let result = self
+            .game
+            .handler()
+            .func(self, account_id, token, data)
+            .await;
+        let metrics = &self.game.metrics;
+        metrics.func_requests.inc();
+        metrics.func_duration.observe(start.elapsed());
+        if result.is_err() {
+            metrics.func_errors.inc();
+        }
+        result
     }
 
     /// 获取此用户所有token状态
     #[inline]
     async fn get_token_status(&self, account_id: i32) -> Result<Vec<GetTokenResult>> {
-        let mut result = GAME
-            .get()
-            .context("not install game")
-            .unwrap()
+        let mut result = self
+            .game
             .peers
             .get_token_state_by_account_id(account_id)
             .await;
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+
+use crate::controller::ProxyController;
+use crate::router::Router;
+
+/// 游戏数据处理器
+/// 可持有游戏状态(配置,随机数,缓存等),每次 func 调用都会转到此处
+#[async_trait::async_trait]
+pub trait GameHandler: Send + Sync {
+    /// 数据request
+    async fn func(
+        &self,
+        controller: &ProxyController,
+        account_id: i32,
+        token: u64,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>>;
+}
+
+#[async_trait::async_trait]
+impl<F> GameHandler for F
+where
+    F: for<'a> Fn(&'a ProxyController, i32, u64, Vec<u8>) -> BoxFuture<'a, Result<Vec<u8>>>
+        + Send
+        + Sync,
+{
+    #[inline]
+    async fn func(
+        &self,
+        controller: &ProxyController,
+        account_id: i32,
+        token: u64,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        self(controller, account_id, token, data).await
+    }
+}
+
+#[async_trait::async_trait]
+impl GameHandler for Router {
+    #[inline]
+    async fn func(
+        &self,
+        controller: &ProxyController,
+        account_id: i32,
+        token: u64,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        self.dispatch(controller, account_id, token, data).await
+    }
+}
+
+/// 将闭包转换成处理器
+/// ``` ignore
+/// let state = Arc::new(MyState::default());
+/// let handler = handler_fn(move |controller, account_id, token, data| {
+///     let state = state.clone();
+///     Box::pin(async move { state.func(controller, account_id, token, data).await })
+/// });
+/// ```
+#[inline]
+pub fn handler_fn<F>(func: F) -> Arc<dyn GameHandler>
+where
+    F: for<'a> Fn(&'a ProxyController, i32, u64, Vec<u8>) -> BoxFuture<'a, Result<Vec<u8>>>
+        + Send
+        + Sync
+        + 'static,
+{
+    Arc::new(func)
+}
@@ -0,0 +1,79 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::http::{listen_http, HttpResponse};
+use crate::services::{IProxyService, MasterConnectionState};
+use crate::Game;
+
+/// 游戏健康状态
+#[derive(Serialize, Debug, Clone)]
+pub struct GameHealth {
+    /// 服务器id
+    pub server_id: u32,
+    /// 是否存活(未关闭)
+    pub live: bool,
+    /// 是否可用(已监听,master已连接且未关闭)
+    /// 服务器由调用者 start 时不检查监听
+    pub ready: bool,
+    /// 是否已通过 serve 开始监听
+    pub listening: bool,
+    /// 是否正在关闭
+    pub shutdown: bool,
+    /// master 连接状态
+    pub master: MasterConnectionState,
+    /// 已注册的代理数量
+    pub proxy_count: usize,
+    /// peer 数量
+    pub peer_count: usize,
+    /// 已连接的 peer 数量
+    pub connected_peer_count: usize,
+}
+
+impl Game {
+    /// 获取健康状态
+    pub async fn health(&self) -> GameHealth {
+        let master = self.master.connection_state();
+        let shutdown = self.is_shutdown();
+        let listening = self.is_listening();
+        let peer_count = self.peers.get_peer_count().await;
+        GameHealth {
+            server_id: self.base_config().server_id,
+            live: !shutdown,
+            ready: !shutdown
+                && (listening || self.is_external_listener())
+                && master == MasterConnectionState::Connected,
+            listening,
+            shutdown,
+            master,
+            proxy_count: self.proxy.count().await,
+            peer_count: peer_count.total,
+            connected_peer_count: peer_count.connected,
+        }
+    }
+
+    /// 启动健康检查 http 服务
+    /// GET /healthz 存活返回200,GET /readyz 可用返回200,否则503,内容为 GameHealth json
+    #[inline]
+    pub(crate) async fn listen_health(self: &Arc<Self>, addr: &str) -> Result<()> {
+        listen_http(self, addr, |game, path| {
+            Box::pin(async move {
+                let health = match path.as_str() {
+                    "/healthz" | "/readyz" => game.health().await,
+                    _ => return Ok(HttpResponse::not_found()),
+                };
+                let ok = if path == "/healthz" {
+                    health.live
+                } else {
+                    health.ready
+                };
+                Ok(HttpResponse::new(
+                    ok,
+                    "application/json",
+                    serde_json::to_string(&health)?,
+                ))
+            })
+        })
+        .await
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::sync::{Arc, Weak};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{timeout, Duration};
+
+use crate::Game;
+
+/// 简易 http 响应
+pub(crate) struct HttpResponse {
+    pub status: &'static str,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl HttpResponse {
+    #[inline]
+    pub fn new(ok: bool, content_type: &'static str, body: String) -> Self {
+        Self {
+            status: if ok {
+                "200 OK"
+            } else {
+                "503 Service Unavailable"
+            },
+            content_type,
+            body,
+        }
+    }
+
+    #[inline]
+    pub fn not_found() -> Self {
+        Self {
+            status: "404 Not Found",
+            content_type: "text/plain",
+            body: String::new(),
+        }
+    }
+}
+
+/// 请求处理函数 参数为游戏实例和请求路径
+pub(crate) type HttpHandler = fn(Arc<Game>, String) -> BoxFuture<'static, Result<HttpResponse>>;
+
+/// 启动简易 http 服务,只处理 GET 请求路径,游戏关闭时终止
+pub(crate) async fn listen_http(game: &Arc<Game>, addr: &str, handler: HttpHandler) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    log::info!("http listen:{addr}");
+    let weak = Arc::downgrade(game);
+    let handle = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let game = weak.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = response(stream, game, handler).await {
+                            log::debug!("http response error:{err}");
+                        }
+                    });
+                }
+                Err(err) => log::error!("http accept error:{err}"),
+            }
+        }
+    });
+    game.add_background(handle.abort_handle());
+    Ok(())
+}
+
+/// 处理一次请求
+async fn response(mut stream: TcpStream, game: Weak<Game>, handler: HttpHandler) -> Result<()> {
+    let mut buf = [0u8; 1024];
+    let len = timeout(Duration::from_secs(5), stream.read(&mut buf)).await??;
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let path = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .unwrap_or_default()
+        .to_string();
+
+    let response = match game.upgrade() {
+        Some(game) => handler(game, path).await?,
+        None => HttpResponse::new(false, "text/plain", String::new()),
+    };
+
+    let data = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    stream.write_all(data.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -1,57 +1,483 @@
+pub mod clock;
 pub mod config;
 pub mod controller;
+pub mod handler;
+pub mod health;
+mod http;
+pub mod metrics;
 pub mod packers;
 pub mod peer;
+pub mod router;
 pub mod services;
 pub mod static_def;
 pub mod time;
 pub mod timer;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, ensure, Result};
+use aqueue::RwModel;
 use futures::future::BoxFuture;
 use netxserver::prelude::NetXServer;
-use once_cell::sync::OnceCell;
-use std::sync::Arc;
+use once_cell::sync::{Lazy, OnceCell};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::Duration;
+use tokio::task::AbortHandle;
+use tokio::time::{sleep, timeout, Instant};
 
+use crate::config::{BaseConfig, Config};
 use crate::controller::{ImplCreateProxyController, ProxyController};
-use crate::services::{ILinkPeerManager, IProxyService};
-use crate::static_def::{BASE_CONFIG, MASTER_SERVICE, PROXY};
+use crate::handler::GameHandler;
+use crate::metrics::Metrics;
+use crate::packers::update::ServerShutdown;
+use crate::packers::IntoResult;
+use crate::router::Router;
+use crate::services::{
+    BroadcastService, HmacTokenGenerator, ILinkPeerManager, IProxyService, MasterService,
+    ProxyService,
+};
+use crate::static_def::{MASTER_SERVICE, PROXY};
+use crate::timer::{AccountAliveTimer, PeerCleanTimer, TimerHandle, TimerManager};
 
 /// 静态安装配置
-pub static GAME: OnceCell<Game> = OnceCell::new();
+/// 仅 Game::init 系列兼容接口会设置
+pub static GAME: OnceCell<Arc<Game>> = OnceCell::new();
 
 /// 数据处理函数指针
 /// 用于外导入
 pub type Func =
     for<'a> fn(&'a ProxyController, i32, u64, Vec<u8>) -> BoxFuture<'a, Result<Vec<u8>>>;
 
-/// 基本安装
+/// 游戏实例
+/// 持有配置,代理管理器,peer管理器,master连接
+/// 一个进程可以运行多个实例
 pub struct Game {
+    /// 配置 base 部分可在线重载
+    config: RwLock<Config>,
+    /// peer 管理器
     pub peers: Arc<dyn ILinkPeerManager>,
-    pub func: Func,
+    /// 代理管理器
+    pub proxy: Arc<RwModel<ProxyService>>,
+    /// master 服务
+    pub master: Arc<MasterService>,
+    /// 广播服务
+    pub broadcast: BroadcastService,
+    /// 指标
+    pub metrics: Arc<Metrics>,
+    /// 定时器 关闭时取消
+    pub timers: TimerManager,
+    /// 内置定时器 重新加载配置时重建
+    housekeeping: Mutex<Vec<TimerHandle>>,
+    handler: Arc<dyn GameHandler>,
+    /// 是否正在关闭
+    shutdown: AtomicBool,
+    /// 正在处理的请求数量
+    requests: AtomicUsize,
+    /// 监听任务
+    listener: Mutex<Option<AbortHandle>>,
+    /// 服务器由调用者 start,不经过 serve
+    external_listener: AtomicBool,
+    /// 后台任务 关闭时终止
+    background: Mutex<Vec<AbortHandle>>,
+}
+
+/// 请求计数
+pub(crate) struct RequestGuard<'a>(&'a AtomicUsize);
+
+impl Drop for RequestGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
 }
 
 impl Game {
+    /// 新建游戏实例
+    #[inline]
+    pub fn new(
+        config: Config,
+        peers: Arc<dyn ILinkPeerManager>,
+        handler: Arc<dyn GameHandler>,
+    ) -> Arc<Self> {
+        let proxy = Arc::new(RwModel::new(ProxyService::default()));
+        let master = Arc::new(MasterService::new(config.master.clone()));
+        Self::with_services(config, peers, proxy, master, handler)
+    }
+
+    #[inline]
+    fn with_services(
+        config: Config,
+        peers: Arc<dyn ILinkPeerManager>,
+        proxy: Arc<RwModel<ProxyService>>,
+        master: Arc<MasterService>,
+        handler: Arc<dyn GameHandler>,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            config: RwLock::new(config),
+            peers,
+            broadcast: BroadcastService::new(proxy.clone()),
+            metrics: Default::default(),
+            timers: Default::default(),
+            housekeeping: Mutex::new(Vec::new()),
+            proxy,
+            master,
+            handler,
+            shutdown: AtomicBool::new(false),
+            requests: AtomicUsize::new(0),
+            listener: Mutex::new(None),
+            external_listener: AtomicBool::new(false),
+            background: Mutex::new(Vec::new()),
+        })
+    }
+
     /// 安装服务
+    #[inline]
     pub async fn init(
         peers: Arc<dyn ILinkPeerManager>,
         func: Func,
     ) -> Result<NetXServer<ImplCreateProxyController>> {
-        GAME.set(Self { peers, func })
+        Self::init_handler(peers, Arc::new(func)).await
+    }
+
+    /// 使用消息路由安装服务
+    #[inline]
+    pub async fn init_router(
+        peers: Arc<dyn ILinkPeerManager>,
+        router: Router,
+    ) -> Result<NetXServer<ImplCreateProxyController>> {
+        Self::init_handler(peers, Arc::new(router)).await
+    }
+
+    /// 使用有状态的处理器安装服务
+    /// 加载默认配置,安装到全局 GAME,并使用 PROXY MASTER_SERVICE
+    #[inline]
+    pub async fn init_handler(
+        peers: Arc<dyn ILinkPeerManager>,
+        handler: Arc<dyn GameHandler>,
+    ) -> Result<NetXServer<ImplCreateProxyController>> {
+        Self::init_with_config(Config::load_default()?, peers, handler).await
+    }
+
+    /// 使用指定配置安装服务
+    /// 安装到全局 GAME,并使用 PROXY MASTER_SERVICE
+    pub async fn init_with_config(
+        config: Config,
+        peers: Arc<dyn ILinkPeerManager>,
+        handler: Arc<dyn GameHandler>,
+    ) -> Result<NetXServer<ImplCreateProxyController>> {
+        ensure!(GAME.get().is_none(), "game is installed");
+        config.validate()?;
+        // MASTER_SERVICE 已被提前使用时沿用同一个
+        let master = Lazy::get(&MASTER_SERVICE)
+            .cloned()
+            .unwrap_or_else(|| Arc::new(MasterService::new(config.master.clone())));
+        let game = Self::with_services(config, peers, PROXY.clone(), master, handler);
+        game.external_listener.store(true, Ordering::Release);
+        GAME.set(game.clone())
             .map_err(|_| anyhow!("not install game"))?;
+        game.start().await
+    }
+
+    /// 数据处理器
+    #[inline]
+    pub fn handler(&self) -> &Arc<dyn GameHandler> {
+        &self.handler
+    }
 
-        if let Err(err) = MASTER_SERVICE.init(BASE_CONFIG.base.server_id).await {
-            log::error!("connect master server error:{}", err);
+    /// 当前配置
+    #[inline]
+    pub fn config(&self) -> Config {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 当前基本配置
+    #[inline]
+    pub fn base_config(&self) -> BaseConfig {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .base
+            .clone()
+    }
+
+    /// 从加载时的配置文件重新加载配置
+    #[inline]
+    pub async fn reload_config(&self) -> Result<()> {
+        let Some(path) = self.config().source().map(Path::to_path_buf) else {
+            bail!("config is not loaded from file, use apply_config")
+        };
+        self.apply_config(Config::load(path)?).await
+    }
+
+    /// 应用新配置
+    /// 只有 base 中除 server_id 外的设置会生效,其余修改会被忽略并打印日志
+    pub async fn apply_config(&self, config: Config) -> Result<()> {
+        Self::check_config(&config)?;
+        let (config, ignored) = self.config().merge_reloadable(&config);
+        for key in ignored {
+            log::warn!("config {key} cannot be changed live, ignored until restart");
+        }
+        self.peers.set_base_config(&config.base).await;
+        let old = self.base_config();
+        if old.housekeeping_timer != config.base.housekeeping_timer
+            || old.keep_alive_interval_sec != config.base.keep_alive_interval_sec
+            || old.peer_clean_timeout_sec != config.base.peer_clean_timeout_sec
+            || old.pending_connect_timeout_sec != config.base.pending_connect_timeout_sec
+        {
+            self.start_housekeeping(&config.base);
+        }
+        log::info!("reload config base:{:?}", config.base);
+        *self.config.write().unwrap_or_else(PoisonError::into_inner) = config;
+        Ok(())
+    }
+
+    /// 监听 SIGHUP 信号重新加载配置
+    #[cfg(unix)]
+    fn listen_reload_signal(self: &Arc<Self>) -> Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = signal(SignalKind::hangup())?;
+        let game = Arc::downgrade(self);
+        let handle = tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                let Some(game) = game.upgrade() else {
+                    break;
+                };
+                log::info!("receive SIGHUP, reload config");
+                if let Err(err) = game.reload_config().await {
+                    log::error!("reload config error:{err:?}");
+                }
+            }
+        });
+        self.add_background(handle.abort_handle());
+        Ok(())
+    }
+
+    /// 检查配置 打印警告,有错误时返回错误
+    #[inline]
+    fn check_config(config: &Config) -> Result<()> {
+        for warning in config.check().warnings {
+            log::warn!("config {warning}");
+        }
+        config.validate()
+    }
+
+    /// 启动服务
+    pub async fn start(self: &Arc<Self>) -> Result<NetXServer<ImplCreateProxyController>> {
+        let config = self.config();
+        Self::check_config(&config)?;
+        self.peers.set_base_config(&config.base).await;
+        self.peers.set_metrics(self.metrics.clone()).await;
+        self.peers.set_proxy(self.proxy.clone()).await;
+        if let Some(ref token) = config.token {
+            self.peers
+                .set_token_generator(Arc::new(HmacTokenGenerator::new(
+                    config.base.server_id,
+                    token.secret.as_bytes(),
+                    token.expire_sec,
+                )?))
+                .await;
+        }
+
+        self.master
+            .init(
+                config.base.server_id,
+                self.peers.clone(),
+                &config.master_connect,
+                self.metrics.clone(),
+            )
+            .await?;
+
+        self.start_housekeeping(&config.base);
+        self.timers.start();
+
+        if let Some(ref health) = config.health {
+            self.listen_health(&health.addr).await?;
+        }
+        if let Some(ref metrics) = config.metrics {
+            self.listen_metrics(&metrics.addr).await?;
+        }
+
+        #[cfg(unix)]
+        if let Err(err) = self.listen_reload_signal() {
+            log::error!("listen SIGHUP error:{err}");
         }
 
         //新建服务器,需要设置和接口实现
-        let server =
-            NetXServer::new(BASE_CONFIG.proxy_listen.clone(), ImplCreateProxyController).await;
-        PROXY
+        let server = NetXServer::new(
+            config.proxy_listen.clone(),
+            ImplCreateProxyController::new(self.clone()),
+        )
+        .await;
+        self.proxy
             .set_manager(server.get_token_manager().upgrade().unwrap())
             .await;
         // 开始服务器,堵塞模式
-        log::info!("starting ns game service:{}", BASE_CONFIG.base.server_id);
+        log::info!("starting ns game service:{}", config.base.server_id);
         Ok(server)
     }
+
+    /// 按配置重建内置定时器
+    fn start_housekeeping(&self, base: &BaseConfig) {
+        let mut housekeeping = self
+            .housekeeping
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for handle in housekeeping.drain(..) {
+            handle.cancel();
+        }
+        if base.housekeeping_timer && !self.is_shutdown() {
+            housekeeping.push(
+                self.timers.add(PeerCleanTimer::new(
+                    self.peers.clone(),
+                    base.peer_clean_timeout_sec
+                        .min(base.pending_connect_timeout_sec),
+                )),
+            );
+            housekeeping.push(self.timers.add(AccountAliveTimer::new(
+                self.peers.clone(),
+                self.master.clone(),
+                base.keep_alive_interval_sec,
+            )));
+        }
+    }
+
+    /// 开始监听并等待,调用 shutdown 后返回
+    pub async fn serve(&self, server: NetXServer<ImplCreateProxyController>) -> Result<()> {
+        let handle = server.start().await?;
+        *self.listener.lock().unwrap_or_else(PoisonError::into_inner) = Some(handle.abort_handle());
+        match handle.await {
+            Ok(result) => result,
+            Err(err) if err.is_cancelled() => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// 服务器是否由调用者 start
+    /// Game::init 系列接口返回的服务器由调用者 start,无法得知是否已监听
+    #[inline]
+    pub fn is_external_listener(&self) -> bool {
+        self.external_listener.load(Ordering::Acquire)
+    }
+
+    /// 是否已通过 serve 开始监听
+    #[inline]
+    pub fn is_listening(&self) -> bool {
+        self.listener
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+
+    /// 添加后台任务,关闭时终止
+    #[inline]
+    pub(crate) fn add_background(&self, handle: AbortHandle) {
+        self.background
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(handle);
+    }
+
+    /// 是否正在关闭
+    #[inline]
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+
+    /// 开始处理请求
+    /// 正在关闭时返回None
+    #[inline]
+    pub(crate) fn begin_request(&self) -> Option<RequestGuard<'_>> {
+        // 先计数再检查 关闭时等待的请求数量不会漏掉已开始的请求
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let guard = RequestGuard(&self.requests);
+        if self.shutdown.load(Ordering::SeqCst) {
+            return None;
+        }
+        Some(guard)
+    }
+
+    /// 关闭服务
+    /// 最长等待 base.shutdown_timeout_sec
+    #[inline]
+    pub async fn shutdown(&self) -> Result<()> {
+        let deadline = Duration::from_secs(self.base_config().shutdown_timeout_sec);
+        self.shutdown_timeout(deadline).await
+    }
+
+    /// 关闭服务
+    /// 停止新建token,新的代理连接和功能调用,通知所有用户,等待正在处理的请求和master请求完成,
+    /// 清理所有peer,最后取消定时器,关闭master连接,代理连接和监听
+    /// 由调用者 start 的服务器不能终止监听,但新的代理连接会被拒绝
+    pub async fn shutdown_timeout(&self, deadline: Duration) -> Result<()> {
+        if self.shutdown.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        let start = Instant::now();
+        let server_id = self.base_config().server_id;
+        log::info!("shutdown ns game service:{server_id}");
+
+        match (ServerShutdown { server_id }).to(None) {
+            Ok(data) => {
+                if let Err(err) = self.broadcast.broadcast_to_server_id(&data).await {
+                    log::error!("broadcast shutdown error:{err}");
+                }
+            }
+            Err(err) => log::error!("serialize shutdown error:{err}"),
+        }
+
+        if timeout(deadline, self.wait_idle()).await.is_err() {
+            log::warn!(
+                "shutdown wait requests timeout, requests:{} master pending:{}",
+                self.requests.load(Ordering::Acquire),
+                self.master.pending_calls().await
+            );
+        }
+
+        if timeout(
+            deadline.saturating_sub(start.elapsed()),
+            self.peers.clear_all(),
+        )
+        .await
+        .is_err()
+        {
+            log::warn!("shutdown clear all peer timeout");
+        }
+
+        self.timers.cancel_all();
+        if let Err(err) = self.master.close().await {
+            log::error!("close master error:{err}");
+        }
+        if let Err(err) = self.proxy.disconnect_all().await {
+            log::error!("disconnect proxy error:{err}");
+        }
+        if let Some(listener) = self
+            .listener
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            listener.abort();
+        }
+        for handle in self
+            .background
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .drain(..)
+        {
+            handle.abort();
+        }
+        log::info!("ns game service:{server_id} is shutdown");
+        Ok(())
+    }
+
+    /// 等待正在处理的请求和master请求完成
+    async fn wait_idle(&self) {
+        while self.requests.load(Ordering::SeqCst) > 0 || self.master.pending_calls().await > 0 {
+            sleep(Duration::from_millis(50)).await;
+        }
+    }
 }
@@ -0,0 +1,421 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::http::{listen_http, HttpResponse};
+use crate::services::{IProxyService, MasterConnectionState};
+use crate::Game;
+
+/// 耗时直方图分段(秒)
+const DURATION_BUCKETS: [f64; 10] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// 计数器
+#[derive(Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1)
+    }
+
+    #[inline]
+    pub fn add(&self, value: u64) {
+        self.0.fetch_add(value, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// 耗时直方图
+pub struct Histogram {
+    buckets: [AtomicU64; DURATION_BUCKETS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: Default::default(),
+            count: AtomicU64::new(0),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    /// 记录一次耗时
+    #[inline]
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (bucket, le) in self.buckets.iter().zip(DURATION_BUCKETS) {
+            if secs <= le {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// 记录次数
+    #[inline]
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+}
+
+/// 按标签值区分的指标
+pub struct Family<T> {
+    items: RwLock<BTreeMap<String, Arc<T>>>,
+}
+
+impl<T> Default for Family<T> {
+    fn default() -> Self {
+        Self {
+            items: Default::default(),
+        }
+    }
+}
+
+impl<T: Default> Family<T> {
+    /// 获取标签值对应的指标,不存在则新建
+    #[inline]
+    pub fn get(&self, label: &str) -> Arc<T> {
+        if let Some(item) = self
+            .items
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(label)
+        {
+            return item.clone();
+        }
+        self.items
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(label.to_string())
+            .or_default()
+            .clone()
+    }
+
+    #[inline]
+    fn snapshot(&self) -> Vec<(String, Arc<T>)> {
+        self.items
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(label, item)| (label.clone(), item.clone()))
+            .collect()
+    }
+}
+
+/// 指标注册表
+#[derive(Default)]
+pub struct Metrics {
+    /// 新建peer数
+    pub peers_created: Counter,
+    /// 清理peer数
+    pub peers_cleaned: Counter,
+    /// 踢下线peer数 标签为原因
+    pub peers_kicked: Family<Counter>,
+    /// 代理注册数
+    pub proxies_registered: Counter,
+    /// 代理断线数
+    pub proxies_disconnected: Counter,
+    /// func(2001)调用数
+    pub func_requests: Counter,
+    /// func(2001)错误数
+    pub func_errors: Counter,
+    /// func(2001)耗时
+    pub func_duration: Histogram,
+    /// 路由消息调用数 标签为消息名
+    pub route_requests: Family<Counter>,
+    /// 路由消息错误数 标签为消息名
+    pub route_errors: Family<Counter>,
+    /// 路由消息耗时 标签为消息名
+    pub route_duration: Family<Histogram>,
+    /// 路由未找到的消息数
+    pub route_not_found: Counter,
+    /// master 请求数 标签为方法名
+    pub master_requests: Family<Counter>,
+    /// master 请求错误数 标签为方法名
+    pub master_errors: Family<Counter>,
+    /// master 请求耗时 标签为方法名
+    pub master_duration: Family<Histogram>,
+}
+
+impl Metrics {
+    /// 统计一次 master 请求
+    #[inline]
+    pub async fn observe_master<T>(
+        &self,
+        method: &str,
+        fut: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let start = Instant::now();
+        let result = fut.await;
+        self.master_requests.get(method).inc();
+        self.master_duration.get(method).observe(start.elapsed());
+        if result.is_err() {
+            self.master_errors.get(method).inc();
+        }
+        result
+    }
+
+    /// 统计一次路由消息
+    #[inline]
+    pub fn observe_route(&self, func: &str, duration: Duration, is_err: bool) {
+        self.route_requests.get(func).inc();
+        self.route_duration.get(func).observe(duration);
+        if is_err {
+            self.route_errors.get(func).inc();
+        }
+    }
+
+    /// 输出 prometheus 文本格式
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_counter(
+            &mut out,
+            "ns_game_peers_created_total",
+            "peers created",
+            &self.peers_created,
+        );
+        write_counter(
+            &mut out,
+            "ns_game_peers_cleaned_total",
+            "peers cleaned",
+            &self.peers_cleaned,
+        );
+        write_counter_family(
+            &mut out,
+            "ns_game_peers_kicked_total",
+            "peers kicked",
+            "reason",
+            &self.peers_kicked,
+        );
+        write_counter(
+            &mut out,
+            "ns_game_proxies_registered_total",
+            "proxies registered",
+            &self.proxies_registered,
+        );
+        write_counter(
+            &mut out,
+            "ns_game_proxies_disconnected_total",
+            "proxies disconnected",
+            &self.proxies_disconnected,
+        );
+        write_counter(
+            &mut out,
+            "ns_game_func_requests_total",
+            "func requests",
+            &self.func_requests,
+        );
+        write_counter(
+            &mut out,
+            "ns_game_func_errors_total",
+            "func requests returned error",
+            &self.func_errors,
+        );
+        write_histogram_header(&mut out, "ns_game_func_duration_seconds", "func duration");
+        write_histogram(
+            &mut out,
+            "ns_game_func_duration_seconds",
+            None,
+            &self.func_duration,
+        );
+        write_counter_family(
+            &mut out,
+            "ns_game_route_requests_total",
+            "routed requests",
+            "func",
+            &self.route_requests,
+        );
+        write_counter_family(
+            &mut out,
+            "ns_game_route_errors_total",
+            "routed requests returned error",
+            "func",
+            &self.route_errors,
+        );
+        write_histogram_family(
+            &mut out,
+            "ns_game_route_duration_seconds",
+            "routed request duration",
+            "func",
+            &self.route_duration,
+        );
+        write_counter(
+            &mut out,
+            "ns_game_route_not_found_total",
+            "requests with unknown func",
+            &self.route_not_found,
+        );
+        write_counter_family(
+            &mut out,
+            "ns_game_master_requests_total",
+            "master requests",
+            "method",
+            &self.master_requests,
+        );
+        write_counter_family(
+            &mut out,
+            "ns_game_master_errors_total",
+            "master requests returned error",
+            "method",
+            &self.master_errors,
+        );
+        write_histogram_family(
+            &mut out,
+            "ns_game_master_duration_seconds",
+            "master request duration",
+            "method",
+            &self.master_duration,
+        );
+        out
+    }
+}
+
+impl Game {
+    /// 输出 prometheus 文本格式,包含当前peer 代理 master状态
+    pub async fn render_metrics(&self) -> String {
+        let mut out = self.metrics.render();
+        let peer_count = self.peers.get_peer_count().await;
+        write_gauge(
+            &mut out,
+            "ns_game_peers",
+            "current peers",
+            peer_count.total as u64,
+        );
+        write_gauge(
+            &mut out,
+            "ns_game_connected_peers",
+            "current connected peers",
+            peer_count.connected as u64,
+        );
+        write_gauge(
+            &mut out,
+            "ns_game_proxies",
+            "current registered proxies",
+            self.proxy.count().await as u64,
+        );
+        write_gauge(
+            &mut out,
+            "ns_game_master_connected",
+            "master connected and registered",
+            (self.master.connection_state() == MasterConnectionState::Connected) as u64,
+        );
+        out
+    }
+
+    /// 启动指标 http 服务 GET /metrics
+    #[inline]
+    pub(crate) async fn listen_metrics(self: &Arc<Self>, addr: &str) -> Result<()> {
+        listen_http(self, addr, |game, path| {
+            Box::pin(async move {
+                if path != "/metrics" {
+                    return Ok(HttpResponse::not_found());
+                }
+                Ok(HttpResponse::new(
+                    true,
+                    "text/plain; version=0.0.4",
+                    game.render_metrics().await,
+                ))
+            })
+        })
+        .await
+    }
+}
+
+#[inline]
+fn write_gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = write!(
+        out,
+        "# HELP {name} {help}\n# TYPE {name} gauge\n{name} {value}\n"
+    );
+}
+
+#[inline]
+fn write_counter(out: &mut String, name: &str, help: &str, counter: &Counter) {
+    let _ = write!(
+        out,
+        "# HELP {name} {help}\n# TYPE {name} counter\n{name} {}\n",
+        counter.get()
+    );
+}
+
+#[inline]
+fn write_counter_family(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    family: &Family<Counter>,
+) {
+    let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} counter\n");
+    for (value, counter) in family.snapshot() {
+        let _ = writeln!(
+            out,
+            "{name}{{{label}=\"{}\"}} {}",
+            escape(&value),
+            counter.get()
+        );
+    }
+}
+
+#[inline]
+fn write_histogram_header(out: &mut String, name: &str, help: &str) {
+    let _ = write!(out, "# HELP {name} {help}\n# TYPE {name} histogram\n");
+}
+
+#[inline]
+fn write_histogram_family(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    family: &Family<Histogram>,
+) {
+    write_histogram_header(out, name, help);
+    for (value, histogram) in family.snapshot() {
+        let label = format!("{label}=\"{}\"", escape(&value));
+        write_histogram(out, name, Some(&label), &histogram);
+    }
+}
+
+fn write_histogram(out: &mut String, name: &str, label: Option<&str>, histogram: &Histogram) {
+    let prefix = label.map(|label| format!("{label},")).unwrap_or_default();
+    for (bucket, le) in histogram.buckets.iter().zip(DURATION_BUCKETS) {
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{prefix}le=\"{le}\"}} {}",
+            bucket.load(Ordering::Relaxed)
+        );
+    }
+    let count = histogram.count();
+    let _ = writeln!(out, "{name}_bucket{{{prefix}le=\"+Inf\"}} {count}");
+    let label = label
+        .map(|label| format!("{{{label}}}"))
+        .unwrap_or_default();
+    let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000f64;
+    let _ = writeln!(out, "{name}_sum{label} {sum}");
+    let _ = writeln!(out, "{name}_count{label} {count}");
+}
+
+/// 转义标签值
+#[inline]
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -86,4 +86,18 @@ pub struct GetTokenResult {
     pub timeout: i64,
     /// websocket是否连接
     pub is_wss_connect: bool,
+    /// token状态
+    pub state: TokenState,
+}
+
+/// token状态
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenState {
+    /// 已新建 还没有连接过
+    Pending,
+    /// 已连接
+    Connected,
+    /// 已断线
+    Disconnected,
 }
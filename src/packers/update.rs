@@ -27,3 +27,51 @@ pub struct MoneyChanged {
     /// vip 等级
     pub vip_level: i32,
 }
+
+/// 服务器关闭通知
+#[derive(Serialize)]
+pub struct ServerShutdown {
+    /// 服务器id
+    pub server_id: u32,
+}
+
+/// 踢下线原因
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KickReason {
+    /// 超过账号peer数量,被新登录挤下线
+    SessionLimit,
+    /// 游戏逻辑踢下线
+    Game,
+    /// master 要求踢下线
+    Master,
+    /// 管理后台踢下线
+    Admin,
+    /// 同一token在其他代理连接
+    Takeover,
+}
+
+impl KickReason {
+    /// 原因名称 用于日志和指标
+    #[inline]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KickReason::SessionLimit => "session_limit",
+            KickReason::Game => "game",
+            KickReason::Master => "master",
+            KickReason::Admin => "admin",
+            KickReason::Takeover => "takeover",
+        }
+    }
+}
+
+/// 被踢下线通知
+#[derive(Serialize)]
+pub struct Kicked {
+    /// 被踢的token
+    pub token: u64,
+    /// 原因
+    pub reason: KickReason,
+    /// 说明 可为空
+    pub message: String,
+}
@@ -0,0 +1,158 @@
+use anyhow::Result;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::IPeer;
+use crate::time::timestamp;
+
+/// BasicPeer 的游戏状态和回调
+/// ``` ignore
+/// #[derive(Default)]
+/// struct MyState { room_id: AtomicI32 }
+///
+/// #[async_trait::async_trait]
+/// impl PeerState for MyState {
+///     fn create(_token: u64, _account_id: i32) -> Self {
+///         Self::default()
+///     }
+/// }
+///
+/// let peers = Arc::new(LinkPeerManager::<BasicPeer<MyState>>::default());
+/// ```
+#[async_trait::async_trait]
+pub trait PeerState: Send + Sync + Sized + 'static {
+    /// 新建状态
+    fn create(token: u64, account_id: i32) -> Self;
+    /// 断线回调
+    async fn on_disconnect(&self, _peer: &BasicPeer<Self>) -> Result<()> {
+        Ok(())
+    }
+    /// 重新连接回调
+    async fn on_reconnect(
+        &self,
+        _peer: &BasicPeer<Self>,
+        _old_proxy_id: usize,
+        _new_proxy_id: usize,
+        _offline_duration: Duration,
+    ) -> Result<()> {
+        Ok(())
+    }
+    /// 清除回调
+    async fn on_clean(&self, _peer: &BasicPeer<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// 通用 PEER
+/// 代理id,断线状态和最后通信时间由 BasicPeer 维护,游戏状态和回调由 S 实现
+/// 新建时断线标记为false,和其他 IPeer 实现一致
+pub struct BasicPeer<S> {
+    token: u64,
+    account_id: i32,
+    proxy_id: AtomicUsize,
+    disconnect: AtomicBool,
+    /// 最后通信时间戳
+    last_time: AtomicI64,
+    state: S,
+}
+
+impl<S> BasicPeer<S> {
+    /// 游戏状态
+    #[inline]
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// 最后通信时间戳
+    #[inline]
+    pub fn last_time(&self) -> i64 {
+        self.last_time.load(Ordering::Acquire)
+    }
+}
+
+impl<S> Display for BasicPeer<S> {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "account_id:{} token:{}", self.account_id, self.token)
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: PeerState> IPeer for BasicPeer<S> {
+    #[inline]
+    fn create(token: u64, account_id: i32) -> Self {
+        Self {
+            token,
+            account_id,
+            proxy_id: AtomicUsize::new(0),
+            disconnect: AtomicBool::new(false),
+            last_time: AtomicI64::new(timestamp()),
+            state: S::create(token, account_id),
+        }
+    }
+
+    #[inline]
+    fn update(&self) {
+        self.last_time.store(timestamp(), Ordering::Release);
+    }
+
+    #[inline]
+    fn get_account_id(&self) -> i32 {
+        self.account_id
+    }
+
+    #[inline]
+    fn get_token(&self) -> u64 {
+        self.token
+    }
+
+    #[inline]
+    fn get_proxy_id(&self) -> usize {
+        self.proxy_id.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn set_proxy_id(&self, proxy_id: usize) {
+        self.proxy_id.store(proxy_id, Ordering::Release);
+    }
+
+    /// 连接和断线都算一次通信
+    #[inline]
+    fn set_disconnect(&self, disconnect: bool) {
+        self.disconnect.store(disconnect, Ordering::Release);
+        self.update();
+    }
+
+    #[inline]
+    fn is_disconnect(&self) -> bool {
+        self.disconnect.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn comparison_time(&self, timestamp: i64) -> i64 {
+        timestamp - self.last_time()
+    }
+
+    #[inline]
+    async fn on_disconnect(&self) -> Result<()> {
+        self.state.on_disconnect(self).await
+    }
+
+    #[inline]
+    async fn on_reconnect(
+        &self,
+        old_proxy_id: usize,
+        new_proxy_id: usize,
+        offline_duration: Duration,
+    ) -> Result<()> {
+        self.state
+            .on_reconnect(self, old_proxy_id, new_proxy_id, offline_duration)
+            .await
+    }
+
+    #[inline]
+    async fn on_clean(&self) -> Result<()> {
+        self.state.on_clean(self).await
+    }
+}
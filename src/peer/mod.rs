@@ -1,5 +1,10 @@
+mod basic;
+
+pub use basic::*;
+
 use anyhow::Result;
 use std::fmt::Display;
+use std::time::Duration;
 
 /// PEER 接口
 #[async_trait::async_trait]
@@ -24,6 +29,16 @@ pub trait IPeer: Display + Send + Sync {
     fn comparison_time(&self, timestamp: i64) -> i64;
     /// 断线回调
     async fn on_disconnect(&self) -> Result<()>;
+    /// 重新连接回调 包括断线后重连和换代理连接
+    /// offline_duration 为断线时长,没有断线直接换代理时为0
+    async fn on_reconnect(
+        &self,
+        _old_proxy_id: usize,
+        _new_proxy_id: usize,
+        _offline_duration: Duration,
+    ) -> Result<()> {
+        Ok(())
+    }
     /// 清除回调
     async fn on_clean(&self) -> Result<()>;
 }
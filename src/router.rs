@@ -0,0 +1,306 @@
+use anyhow::Result;
+use futures::future::BoxFuture;
+use netxserver::prelude::NetxToken;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::controller::ProxyController;
+use crate::packers::error::format_gen_error;
+use crate::packers::IntoResult;
+use crate::Game;
+
+/// 路由调用上下文
+pub struct RouteContext {
+    /// 游戏实例
+    pub game: Arc<Game>,
+    /// 代理连接
+    pub proxy: NetxToken<ProxyController>,
+    /// 代理id
+    pub proxy_id: usize,
+    /// 账号id
+    pub account_id: i32,
+    /// peer token
+    pub token: u64,
+    /// 请求序号
+    pub serial: Option<i64>,
+}
+
+/// 客户端请求包
+#[derive(Deserialize)]
+struct RequestJson {
+    #[serde(default)]
+    serial: Option<i64>,
+    func: String,
+    #[serde(default = "empty_context")]
+    context: Value,
+}
+
+#[inline]
+fn empty_context() -> Value {
+    Value::Object(Default::default())
+}
+
+/// 已解析 context 等待调用的处理函数
+type BoundRoute = Box<dyn FnOnce(RouteContext) -> BoxFuture<'static, Result<Vec<u8>>> + Send>;
+
+type RouteFn = Box<dyn Fn(Value) -> serde_json::Result<BoundRoute> + Send + Sync>;
+
+/// 已找到处理函数的请求
+struct Routed {
+    func: String,
+    serial: Option<i64>,
+    call: BoundRoute,
+}
+
+/// 路由失败 回包为通用错误
+#[derive(Debug)]
+enum RouteError {
+    /// 请求包格式错误
+    Request(serde_json::Error),
+    /// 没有注册此消息
+    NotFound { serial: Option<i64>, func: String },
+    /// context 格式错误
+    Context {
+        serial: Option<i64>,
+        func: String,
+        err: serde_json::Error,
+    },
+}
+
+impl RouteError {
+    /// 错误回包 有serial时带上请求的serial
+    #[inline]
+    fn reply(&self) -> Result<Vec<u8>> {
+        match self {
+            RouteError::Request(err) => {
+                format_gen_error(None, 0, format!("request error:{err}").into())
+            }
+            RouteError::NotFound { serial, func } => {
+                format_gen_error(*serial, 0, format!("not found func:{func}").into())
+            }
+            RouteError::Context { serial, func, err } => format_gen_error(
+                *serial,
+                0,
+                format!("func:{func} context error:{err}").into(),
+            ),
+        }
+    }
+}
+
+/// 消息路由器
+/// 按消息名(func)注册处理函数,自动反序列化 context 并序列化返回
+/// ``` ignore
+/// let router = Router::new()
+///     .route("Ping", |_ctx, ping: Ping| async move { Ok(Pong { tick: ping.tick }) });
+/// ```
+#[derive(Default)]
+pub struct Router {
+    routes: HashMap<String, RouteFn>,
+}
+
+impl Router {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册处理函数,返回值按请求serial序列化
+    #[inline]
+    pub fn route<Req, Resp, F, Fut>(self, name: impl Into<String>, handler: F) -> Self
+    where
+        Req: DeserializeOwned + Send + 'static,
+        Resp: Serialize,
+        F: Fn(RouteContext, Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Resp>> + Send + 'static,
+    {
+        self.route_raw(name, move |ctx: RouteContext, req: Req| {
+            let serial = ctx.serial;
+            let fut = handler(ctx, req);
+            async move { fut.await?.to(serial) }
+        })
+    }
+
+    /// 注册处理函数,返回值由处理函数自行序列化
+    /// 可配合 ret! ret_error! 等宏使用
+    #[inline]
+    pub fn route_raw<Req, F, Fut>(mut self, name: impl Into<String>, handler: F) -> Self
+    where
+        Req: DeserializeOwned + Send + 'static,
+        F: Fn(RouteContext, Req) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<u8>>> + Send + 'static,
+    {
+        let name = name.into();
+        let handler = Arc::new(handler);
+        let route: RouteFn = Box::new(move |context| {
+            let req = serde_json::from_value::<Req>(context)?;
+            let handler = handler.clone();
+            Ok(Box::new(move |ctx| Box::pin(handler(ctx, req))))
+        });
+        if self.routes.insert(name.clone(), route).is_some() {
+            log::warn!("router func:{name} is replaced");
+        }
+        self
+    }
+
+    /// 是否注册了此消息
+    #[inline]
+    pub fn contains(&self, name: &str) -> bool {
+        self.routes.contains_key(name)
+    }
+
+    /// 解析请求包并找到处理函数
+    fn resolve(&self, data: &[u8]) -> std::result::Result<Routed, RouteError> {
+        let request = serde_json::from_slice::<RequestJson>(data).map_err(RouteError::Request)?;
+        let Some(route) = self.routes.get(&request.func) else {
+            return Err(RouteError::NotFound {
+                serial: request.serial,
+                func: request.func,
+            });
+        };
+        match route(request.context) {
+            Ok(call) => Ok(Routed {
+                func: request.func,
+                serial: request.serial,
+                call,
+            }),
+            Err(err) => Err(RouteError::Context {
+                serial: request.serial,
+                func: request.func,
+                err,
+            }),
+        }
+    }
+
+    /// 分发请求
+    #[inline]
+    pub async fn dispatch(
+        &self,
+        controller: &ProxyController,
+        account_id: i32,
+        token: u64,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let metrics = &controller.game.metrics;
+        let routed = match self.resolve(&data) {
+            Ok(routed) => routed,
+            Err(err) => {
+                match &err {
+                    RouteError::NotFound { .. } => metrics.route_not_found.inc(),
+                    RouteError::Context { func, .. } => {
+                        metrics.observe_route(func, start.elapsed(), false)
+                    }
+                    RouteError::Request(_) => {}
+                }
+                return err.reply();
+            }
+        };
+
+        let ctx = RouteContext {
+            game: controller.game.clone(),
+            proxy: controller.token.clone(),
+            proxy_id: controller.proxy_id.load(Ordering::Acquire),
+            account_id,
+            token,
+            serial: routed.serial,
+        };
+        let result = (routed.call)(ctx).await;
+        metrics.observe_route(&routed.func, start.elapsed(), result.is_err());
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packers::{Ping, Pong};
+
+    fn router() -> Router {
+        Router::new().route("Ping", |_ctx, ping: Ping| async move {
+            Ok(Pong { tick: ping.tick })
+        })
+    }
+
+    fn reply(err: RouteError) -> Value {
+        serde_json::from_slice(&err.reply().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn found_keeps_serial() {
+        let router = router();
+        let Ok(routed) = router.resolve(br#"{"serial":7,"func":"Ping","context":{"tick":1}}"#)
+        else {
+            panic!("route not found");
+        };
+        assert_eq!(routed.func, "Ping");
+        assert_eq!(routed.serial, Some(7));
+
+        let Ok(routed) = router.resolve(br#"{"func":"Ping","context":{"tick":1}}"#) else {
+            panic!("route not found");
+        };
+        assert_eq!(routed.serial, None);
+    }
+
+    #[test]
+    fn unknown_func() {
+        let Err(err) = router().resolve(br#"{"serial":3,"func":"Pong"}"#) else {
+            panic!("unknown func routed");
+        };
+        assert!(matches!(
+            err,
+            RouteError::NotFound {
+                serial: Some(3),
+                ..
+            }
+        ));
+        let reply = reply(err);
+        assert_eq!(reply["serial"], 3);
+        assert_eq!(reply["func"], "GeneralError");
+        assert_eq!(reply["context"]["msg"], "not found func:Pong");
+    }
+
+    #[test]
+    fn bad_context_keeps_serial() {
+        let Err(err) = router().resolve(br#"{"serial":5,"func":"Ping","context":{"tick":"x"}}"#)
+        else {
+            panic!("bad context routed");
+        };
+        assert!(matches!(
+            err,
+            RouteError::Context {
+                serial: Some(5),
+                ..
+            }
+        ));
+        let reply = reply(err);
+        assert_eq!(reply["serial"], 5);
+        assert_eq!(reply["func"], "GeneralError");
+        assert!(reply["context"]["msg"]
+            .as_str()
+            .unwrap()
+            .starts_with("func:Ping context error:"));
+
+        // 缺少 context 时按空对象解析
+        let Err(err) = router().resolve(br#"{"func":"Ping"}"#) else {
+            panic!("empty context routed");
+        };
+        assert!(matches!(err, RouteError::Context { serial: None, .. }));
+    }
+
+    #[test]
+    fn bad_request() {
+        let Err(err) = router().resolve(b"{") else {
+            panic!("bad request routed");
+        };
+        assert!(matches!(err, RouteError::Request(_)));
+        let reply = reply(err);
+        assert!(reply.get("serial").is_none());
+        assert_eq!(reply["func"], "GeneralError");
+    }
+}
@@ -1,19 +1,25 @@
-use crate::controller::{IProxy, ___impl_IProxy_call};
-use crate::services::IProxyService;
-use crate::static_def::PROXY;
+use crate::controller::{___impl_IProxy_call, IProxy};
+use crate::services::{IProxyService, ProxyService};
 use anyhow::Result;
+use aqueue::RwModel;
 use netxserver::prelude::*;
+use std::sync::Arc;
 
 /// 广播服务
-#[derive(Default)]
-pub struct BroadcastService;
+pub struct BroadcastService {
+    proxy: Arc<RwModel<ProxyService>>,
+}
 
 impl BroadcastService {
+    pub fn new(proxy: Arc<RwModel<ProxyService>>) -> Self {
+        Self { proxy }
+    }
+
     /// 广播到 所有服务器的此玩家 所有连接
     /// 一般用于资产发生变化
     #[inline]
     pub async fn broadcast_to_account_id(&self, account_id: i32, data: &[u8]) -> Result<()> {
-        for netx_token in PROXY.get_all_token().await? {
+        for netx_token in self.proxy.get_all_token().await? {
             let proxy = impl_ref!(netx_token=>IProxy);
             proxy.broadcast_to_account_id(account_id, data).await;
         }
@@ -24,7 +30,7 @@ impl BroadcastService {
     /// 一般用于紧急公告 跑马灯等
     #[inline]
     pub async fn broadcast_to_all_users(&self, data: &[u8]) -> Result<()> {
-        for netx_token in PROXY.get_all_token().await? {
+        for netx_token in self.proxy.get_all_token().await? {
             let proxy = impl_ref!(netx_token=>IProxy);
             proxy.broadcast_to_all_users(data).await;
         }
@@ -35,7 +41,7 @@ impl BroadcastService {
     /// 一般用于游戏内部通知
     #[inline]
     pub async fn broadcast_to_server_id(&self, data: &[u8]) -> Result<()> {
-        for netx_token in PROXY.get_all_token().await? {
+        for netx_token in self.proxy.get_all_token().await? {
             let proxy = impl_ref!(netx_token=>IProxy);
             proxy.broadcast_to_server_id(0, data).await;
         }
@@ -50,7 +56,7 @@ impl BroadcastService {
         account_id: i32,
         data: &[u8],
     ) -> Result<()> {
-        for netx_token in PROXY.get_all_token().await? {
+        for netx_token in self.proxy.get_all_token().await? {
             let proxy = impl_ref!(netx_token=>IProxy);
             proxy
                 .broadcast_to_server_id_and_account_id(0, account_id, data)
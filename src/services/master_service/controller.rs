@@ -1,6 +1,9 @@
-use crate::GAME;
-use anyhow::{Context, Result};
+use crate::packers::update::KickReason;
+use crate::services::master_service::{ConnectionState, MasterConnectionState};
+use crate::services::ILinkPeerManager;
+use anyhow::Result;
 use netxclient::prelude::*;
+use std::sync::Arc;
 
 use super::interface::*;
 
@@ -8,11 +11,23 @@ use super::interface::*;
 pub struct MasterController {
     server_id: u32,
     server: NetxClientArcDef,
+    peers: Arc<dyn ILinkPeerManager>,
+    state: Arc<ConnectionState>,
 }
 
 impl MasterController {
-    pub fn new(server_id: u32, server: NetxClientArcDef) -> Self {
-        Self { server_id, server }
+    pub(crate) fn new(
+        server_id: u32,
+        server: NetxClientArcDef,
+        peers: Arc<dyn ILinkPeerManager>,
+        state: Arc<ConnectionState>,
+    ) -> Self {
+        Self {
+            server_id,
+            server,
+            peers,
+            state,
+        }
     }
 }
 
@@ -26,6 +41,12 @@ pub trait IMasterController {
     /// ping
     #[tag(1000)]
     async fn ping(&self, time: i64) -> Result<i64>;
+    /// 踢掉此账号的所有peer 返回踢掉的数量
+    #[tag(1010)]
+    async fn kick_account(&self, account_id: i32, message: String) -> Result<usize>;
+    /// 踢掉token 返回false表示没找到
+    #[tag(1011)]
+    async fn kick_token(&self, token: u64, message: String) -> Result<bool>;
 }
 
 #[build_impl]
@@ -34,6 +55,7 @@ impl IMasterController for MasterController {
     async fn connected(&self) -> Result<()> {
         let server = impl_ref!(self.server=>IMaster);
         if server.register_slot_server(self.server_id).await? {
+            self.state.set(MasterConnectionState::Connected);
             log::info!("connect master server Ok");
         } else {
             panic!("register server id fail,check server id!!")
@@ -45,11 +67,8 @@ impl IMasterController for MasterController {
     async fn disconnect(&self) -> Result<()> {
         //和大厅断线处理
         //清除所有的token 和peer
-        GAME.get()
-            .context("not found GAME?")?
-            .peers
-            .clear_all()
-            .await;
+        self.state.set(MasterConnectionState::Disconnected);
+        self.peers.clear_all().await;
         Ok(())
     }
 
@@ -59,4 +78,22 @@ impl IMasterController for MasterController {
         log::debug!("master ping:{time}");
         Ok(time)
     }
+
+    #[inline]
+    async fn kick_account(&self, account_id: i32, message: String) -> Result<usize> {
+        log::info!("master kick account:{account_id} message:{message}");
+        Ok(self
+            .peers
+            .kick_account(account_id, KickReason::Master, message)
+            .await)
+    }
+
+    #[inline]
+    async fn kick_token(&self, token: u64, message: String) -> Result<bool> {
+        log::info!("master kick token:{token} message:{message}");
+        Ok(self
+            .peers
+            .kick_token(token, KickReason::Master, message)
+            .await)
+    }
 }
@@ -1,41 +1,202 @@
 mod controller;
 mod interface;
 
+use crate::config::{MasterConnectConfig, MasterStartupPolicy};
+use crate::metrics::Metrics;
 use crate::services::master_service::controller::MasterController;
-use crate::GAME;
-use anyhow::Result;
+use crate::services::ILinkPeerManager;
+use anyhow::{Context, Result};
 pub use interface::*;
 use netxclient::prelude::*;
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+
+/// master 连接状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[repr(u8)]
+pub enum MasterConnectionState {
+    /// 未连接
+    Disconnected = 0,
+    /// 连接中
+    Connecting = 1,
+    /// 已连接并注册
+    Connected = 2,
+    /// 已关闭
+    Closed = 3,
+}
+
+/// 共享的连接状态
+#[derive(Default)]
+pub(crate) struct ConnectionState(AtomicU8);
+
+impl ConnectionState {
+    #[inline]
+    pub(crate) fn get(&self) -> MasterConnectionState {
+        match self.0.load(Ordering::Acquire) {
+            1 => MasterConnectionState::Connecting,
+            2 => MasterConnectionState::Connected,
+            3 => MasterConnectionState::Closed,
+            _ => MasterConnectionState::Disconnected,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set(&self, state: MasterConnectionState) {
+        // 已关闭后不再变更
+        let _ = self
+            .0
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+                (current != MasterConnectionState::Closed as u8).then_some(state as u8)
+            });
+    }
+}
 
 /// Master 服务
 pub struct MasterService {
     client: NetxClientArcDef,
+    peers: OnceCell<Arc<dyn ILinkPeerManager>>,
+    metrics: OnceCell<Arc<Metrics>>,
+    state: Arc<ConnectionState>,
 }
 
 impl MasterService {
     pub fn new(config: ServerOption) -> Self {
         let client = NetXClient::new(config, DefaultSessionStore::default());
-        Self { client }
+        Self {
+            client,
+            peers: OnceCell::new(),
+            metrics: OnceCell::new(),
+            state: Default::default(),
+        }
     }
 
-    /// 安装控制器
-    pub(crate) async fn init(&self, server_id: u32) -> Result<()> {
+    /// 安装控制器,并按策略连接
+    pub(crate) async fn init(
+        &self,
+        server_id: u32,
+        peers: Arc<dyn ILinkPeerManager>,
+        connect: &MasterConnectConfig,
+        metrics: Arc<Metrics>,
+    ) -> Result<()> {
+        let _ = self.peers.set(peers.clone());
+        let _ = self.metrics.set(metrics);
         self.client
-            .init(MasterController::new(server_id, self.client.clone()))
+            .init(MasterController::new(
+                server_id,
+                self.client.clone(),
+                peers,
+                self.state.clone(),
+            ))
             .await?;
 
-        self.client.connect_network().await
+        match connect.startup_policy {
+            MasterStartupPolicy::FailFast => connect_master(&self.client, &self.state).await?,
+            MasterStartupPolicy::Retry => {
+                let mut attempt = 0;
+                while let Err(err) = connect_master(&self.client, &self.state).await {
+                    if attempt >= connect.retry_max {
+                        return Err(err).context(format!(
+                            "connect master server fail after {attempt} retries"
+                        ));
+                    }
+                    let wait = connect.backoff_ms(attempt);
+                    log::warn!("connect master server error:{err}, retry after {wait}ms");
+                    sleep(Duration::from_millis(wait)).await;
+                    attempt += 1;
+                }
+            }
+            MasterStartupPolicy::Background => {
+                if let Err(err) = connect_master(&self.client, &self.state).await {
+                    log::error!("connect master server error:{}", err);
+                }
+            }
+        }
+
+        if connect.reconnect {
+            self.spawn_reconnect(connect.clone());
+        }
+        Ok(())
+    }
+
+    /// 后台重连
+    fn spawn_reconnect(&self, connect: MasterConnectConfig) {
+        let client = self.client.clone();
+        let state = self.state.clone();
+        tokio::spawn(async move {
+            let mut attempt = 0;
+            loop {
+                if client.is_connect() {
+                    attempt = 0;
+                    sleep(Duration::from_millis(connect.retry_initial_ms)).await;
+                } else {
+                    sleep(Duration::from_millis(connect.backoff_ms(attempt))).await;
+                }
+                if state.get() == MasterConnectionState::Closed {
+                    break;
+                }
+                if !client.is_connect() {
+                    match connect_master(&client, &state).await {
+                        Ok(_) => log::info!("reconnect master server ok"),
+                        Err(err) => {
+                            attempt = attempt.saturating_add(1);
+                            log::warn!("reconnect master server error:{err} attempt:{attempt}");
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// 连接状态
+    #[inline]
+    pub fn connection_state(&self) -> MasterConnectionState {
+        self.state.get()
+    }
+
+    /// 等待中的master请求数量
+    #[inline]
+    pub async fn pending_calls(&self) -> usize {
+        self.client.get_callback_len().await
+    }
+
+    /// 关闭master连接
+    #[inline]
+    pub(crate) async fn close(&self) -> Result<()> {
+        self.state.set(MasterConnectionState::Closed);
+        self.client.close().await
+    }
+
+    /// 统计请求
+    #[inline]
+    async fn observe<T>(&self, method: &str, fut: impl Future<Output = Result<T>>) -> Result<T> {
+        match self.metrics.get() {
+            Some(metrics) => metrics.observe_master(method, fut).await,
+            None => fut.await,
+        }
+    }
+
+    /// 清理此账号的peer
+    #[inline]
+    async fn clean_by_account_id(&self, account_id: i32) {
+        if let Some(peers) = self.peers.get() {
+            peers.clean_by_account_id(account_id).await;
+        }
     }
 
     /// 获取玩家基本信息
     #[inline]
     pub async fn get_account_info(&self, account_id: i32) -> Result<Option<AccountInfoRet>> {
         let server = impl_ref!(self.client=>IMaster);
-        let result = server.get_player_info(account_id).await?;
+        let result = self
+            .observe("get_player_info", server.get_player_info(account_id))
+            .await?;
         if result.is_none() {
-            if let Some(game) = GAME.get() {
-                game.peers.clean_by_account_id(account_id).await;
-            }
+            self.clean_by_account_id(account_id).await;
         }
         Ok(result)
     }
@@ -49,11 +210,14 @@ impl MasterService {
         req: ReqSlotSpin,
     ) -> Result<SlotSpinRet> {
         let server = impl_ref!(self.client=>IMaster);
-        let result = server.req_slot_spin(account_id, token, req).await;
+        let result = self
+            .observe(
+                "req_slot_spin",
+                server.req_slot_spin(account_id, token, req),
+            )
+            .await;
         if result.is_err() {
-            if let Some(game) = GAME.get() {
-                game.peers.clean_by_account_id(account_id).await;
-            }
+            self.clean_by_account_id(account_id).await;
         }
         result
     }
@@ -67,11 +231,14 @@ impl MasterService {
         req: ReqSlotRefund,
     ) -> Result<SlotRefundRet> {
         let server = impl_ref!(self.client=>IMaster);
-        let result = server.req_slot_refund(account_id, token, req).await;
+        let result = self
+            .observe(
+                "req_slot_refund",
+                server.req_slot_refund(account_id, token, req),
+            )
+            .await;
         if result.is_err() {
-            if let Some(game) = GAME.get() {
-                game.peers.clean_by_account_id(account_id).await;
-            }
+            self.clean_by_account_id(account_id).await;
         }
         result
     }
@@ -80,18 +247,22 @@ impl MasterService {
     #[inline]
     pub async fn get_lottery_info(&self, game_id: u32) -> Result<Vec<LotteryInfo>> {
         let server = impl_ref!(self.client=>IMaster);
-        server.get_game_lottery(game_id).await
+        self.observe("get_game_lottery", server.get_game_lottery(game_id))
+            .await
     }
 
     /// 从money cache 移动钱到 money
     #[inline]
     pub async fn move_money_cache(&self, account_id: i32, money: i64) -> Result<MoneyContext> {
         let server = impl_ref!(self.client=>IMaster);
-        let result = server.move_money_cache(account_id, money).await;
+        let result = self
+            .observe(
+                "move_money_cache",
+                server.move_money_cache(account_id, money),
+            )
+            .await;
         if result.is_err() {
-            if let Some(game) = GAME.get() {
-                game.peers.clean_by_account_id(account_id).await;
-            }
+            self.clean_by_account_id(account_id).await;
         }
         result
     }
@@ -105,15 +276,31 @@ impl MasterService {
         coin: f64,
     ) -> Result<i64> {
         let server = impl_ref!(self.client=>IMaster);
-        server
-            .robot_lottery_spin(loop_count, lottery_id, coin)
-            .await
+        self.observe(
+            "robot_lottery_spin",
+            server.robot_lottery_spin(loop_count, lottery_id, coin),
+        )
+        .await
     }
 
     /// peer账号信息保活 不结存
     #[inline]
     pub async fn alive_account(&self, account_ids: &[i32]) {
         let server = impl_ref!(self.client=>IMaster);
+        if let Some(metrics) = self.metrics.get() {
+            metrics.master_requests.get("alive_account").inc();
+        }
         server.alive_account(account_ids).await
     }
 }
+
+/// 连接master,连接成功后由控制器注册服务器
+#[inline]
+async fn connect_master(client: &NetxClientArcDef, state: &ConnectionState) -> Result<()> {
+    state.set(MasterConnectionState::Connecting);
+    if let Err(err) = client.connect_network().await {
+        state.set(MasterConnectionState::Disconnected);
+        return Err(err);
+    }
+    Ok(())
+}
@@ -2,8 +2,10 @@ mod broadcast_service;
 mod master_service;
 mod peers_service;
 mod proxy_service;
+mod token_service;
 
 pub use broadcast_service::*;
 pub use master_service::*;
 pub use peers_service::*;
 pub use proxy_service::*;
+pub use token_service::*;
@@ -1,37 +1,386 @@
-use crate::packers::GetTokenResult;
-use anyhow::{bail, ensure, Result};
-use aqueue::Actor;
-use std::collections::HashMap;
-use std::sync::Arc;
+use crate::packers::update::{KickReason, Kicked};
+use crate::packers::{GetTokenResult, IntoResult, TokenState};
+use anyhow::{anyhow, bail, ensure, Result};
+use aqueue::RwModel;
+use netxserver::prelude::*;
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+use tokio::sync::broadcast;
 
+use crate::config::{BaseConfig, SessionLimitPolicy};
+use crate::controller::{___impl_IProxy_call, IProxy};
+use crate::metrics::Metrics;
 use crate::peer::IPeer;
-use crate::static_def::BASE_CONFIG;
-use crate::time::{timestamp, timestamp_nanos, SECOND, TICK};
+use crate::services::{IProxyService, ProxyService, RandomTokenGenerator, TokenGenerator};
+use crate::time::{timestamp, SECOND, TICK};
+
+/// 生成token冲突时最多重试次数
+const CREATE_TOKEN_RETRY: usize = 16;
+
+/// 分片数量 必须是2的幂
+const SHARDS: usize = 64;
+
+/// 事件通道容量 接收慢于此数量会收到 Lagged
+const PEER_EVENT_CAPACITY: usize = 1024;
+
+/// PEER 数量
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PeerCount {
+    /// 总数
+    pub total: usize,
+    /// 已连接数量
+    pub connected: usize,
+}
+
+/// peer 扩展数据 按类型保存
+pub type Extension = Arc<dyn Any + Send + Sync>;
+
+/// PEER 生命周期事件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerEvent {
+    /// 新建token
+    Created { token: u64, account_id: i32 },
+    /// 首次连接
+    Connected {
+        token: u64,
+        account_id: i32,
+        proxy_id: usize,
+    },
+    /// 断线后重新连接
+    Reconnected {
+        token: u64,
+        account_id: i32,
+        old_proxy_id: usize,
+        proxy_id: usize,
+    },
+    /// 断线
+    Disconnected {
+        token: u64,
+        account_id: i32,
+        proxy_id: usize,
+    },
+    /// 被踢下线 之后还会发送 Cleaned
+    Kicked {
+        token: u64,
+        account_id: i32,
+        reason: KickReason,
+    },
+    /// 已删除 on_clean 已运行
+    Cleaned { token: u64, account_id: i32 },
+}
+
+impl PeerEvent {
+    /// token
+    #[inline]
+    pub fn token(&self) -> u64 {
+        match *self {
+            PeerEvent::Created { token, .. }
+            | PeerEvent::Connected { token, .. }
+            | PeerEvent::Reconnected { token, .. }
+            | PeerEvent::Disconnected { token, .. }
+            | PeerEvent::Kicked { token, .. }
+            | PeerEvent::Cleaned { token, .. } => token,
+        }
+    }
+
+    /// 账号id
+    #[inline]
+    pub fn account_id(&self) -> i32 {
+        match *self {
+            PeerEvent::Created { account_id, .. }
+            | PeerEvent::Connected { account_id, .. }
+            | PeerEvent::Reconnected { account_id, .. }
+            | PeerEvent::Disconnected { account_id, .. }
+            | PeerEvent::Kicked { account_id, .. }
+            | PeerEvent::Cleaned { account_id, .. } => account_id,
+        }
+    }
+}
+
+/// 按token分片的peer
+struct PeerShard<T> {
+    peers: HashMap<u64, Arc<T>>,
+    /// 代理id索引
+    proxy_index: HashMap<usize, HashSet<u64>>,
+    /// 还没有连接过的token 和新建时间戳
+    pending: HashMap<u64, i64>,
+    /// 已断线的token 和断线时间戳
+    offline: HashMap<u64, i64>,
+    /// 扩展数据
+    extensions: HashMap<u64, HashMap<TypeId, Extension>>,
+}
+
+impl<T> Default for PeerShard<T> {
+    fn default() -> Self {
+        Self {
+            peers: Default::default(),
+            proxy_index: Default::default(),
+            pending: Default::default(),
+            offline: Default::default(),
+            extensions: Default::default(),
+        }
+    }
+}
+
+impl<T: IPeer> PeerShard<T> {
+    #[inline]
+    fn insert(&mut self, peer: Arc<T>) {
+        let token = peer.get_token();
+        self.pending.insert(token, timestamp());
+        self.proxy_index
+            .entry(peer.get_proxy_id())
+            .or_default()
+            .insert(token);
+        self.peers.insert(token, peer);
+    }
+
+    #[inline]
+    fn remove(&mut self, token: u64) -> Option<Arc<T>> {
+        let peer = self.peers.remove(&token)?;
+        self.pending.remove(&token);
+        self.offline.remove(&token);
+        self.extensions.remove(&token);
+        self.remove_proxy_index(peer.get_proxy_id(), token);
+        Some(peer)
+    }
+
+    #[inline]
+    fn remove_proxy_index(&mut self, proxy_id: usize, token: u64) {
+        if let Some(tokens) = self.proxy_index.get_mut(&proxy_id) {
+            tokens.remove(&token);
+            if tokens.is_empty() {
+                self.proxy_index.remove(&proxy_id);
+            }
+        }
+    }
+}
+
+/// 按账号id分片的账号索引 token按创建顺序
+type AccountShard = RwLock<HashMap<i32, Vec<u64>>>;
+
+/// 可在线修改的设置
+#[derive(Clone, Copy)]
+struct Settings {
+    /// peer 没通信多久清理(秒)
+    peer_clean_timeout_sec: i64,
+    /// 新建token多久没有连接清理(秒)
+    pending_connect_timeout_sec: i64,
+    /// 每个账号最多peer数量 0不限制
+    max_peers_per_account: usize,
+    /// 超过账号peer数量时的处理策略
+    session_limit_policy: SessionLimitPolicy,
+    /// 已连接的token是否允许从其他代理连接
+    allow_connect_takeover: bool,
+}
 
 /// PEER管理器
+/// peer 按token分片,账号索引按账号id分片,读写都不经过单一队列
+/// 加锁顺序为 账号分片 -> token分片,锁内不调用 IPeer 的异步回调
 pub struct LinkPeerManager<T> {
-    peers: HashMap<u64, Arc<T>>,
+    /// 按token分片的peer
+    shards: Box<[RwLock<PeerShard<T>>]>,
+    /// 按账号id分片的账号索引 按创建顺序
+    accounts: Box<[AccountShard]>,
+    settings: RwLock<Settings>,
+    /// 代理管理器 用于通知客户端
+    proxy: RwLock<Option<Arc<RwModel<ProxyService>>>>,
+    /// 指标
+    metrics: RwLock<Arc<Metrics>>,
+    /// token 生成器
+    token_generator: RwLock<Arc<dyn TokenGenerator>>,
+    /// 生命周期事件
+    events: broadcast::Sender<PeerEvent>,
 }
 
 impl<T> Default for LinkPeerManager<T> {
     fn default() -> Self {
         Self {
-            peers: Default::default(),
+            shards: (0..SHARDS).map(|_| Default::default()).collect(),
+            accounts: (0..SHARDS).map(|_| Default::default()).collect(),
+            settings: RwLock::new(Settings {
+                peer_clean_timeout_sec: 300,
+                pending_connect_timeout_sec: 60,
+                max_peers_per_account: 0,
+                session_limit_policy: Default::default(),
+                allow_connect_takeover: false,
+            }),
+            proxy: Default::default(),
+            metrics: Default::default(),
+            token_generator: RwLock::new(Arc::new(RandomTokenGenerator)),
+            events: broadcast::Sender::new(PEER_EVENT_CAPACITY),
         }
     }
 }
 
+#[inline]
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[inline]
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
 impl<T: IPeer + 'static> LinkPeerManager<T> {
-    ///新建PEER
     #[inline]
-    fn create_peer(&mut self, account_id: i32) -> Result<u64> {
-        let token = timestamp_nanos() % 100000000000000;
+    fn shard(&self, token: u64) -> &RwLock<PeerShard<T>> {
+        &self.shards[token as usize & (SHARDS - 1)]
+    }
+
+    #[inline]
+    fn account_shard(&self, account_id: i32) -> &AccountShard {
+        &self.accounts[account_id as u32 as usize & (SHARDS - 1)]
+    }
 
-        ensure!(!self.peers.contains_key(&token), "token exits");
+    #[inline]
+    fn settings(&self) -> Settings {
+        *read(&self.settings)
+    }
 
-        self.peers
-            .insert(token, Arc::new(IPeer::create(token, account_id)));
+    #[inline]
+    fn metrics(&self) -> Arc<Metrics> {
+        read(&self.metrics).clone()
+    }
 
+    /// 此账号的所有token 按创建顺序
+    #[inline]
+    fn account_tokens(&self, account_id: i32) -> Vec<u64> {
+        read(self.account_shard(account_id))
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// 删除peer并更新索引
+    /// filter 参数为peer和还没连接时的新建时间,返回false时不删除
+    fn remove_peer_if(
+        &self,
+        token: u64,
+        filter: impl FnOnce(&T, Option<i64>) -> bool,
+    ) -> Option<Arc<T>> {
+        let account_id = read(self.shard(token)).peers.get(&token)?.get_account_id();
+        let mut accounts = write(self.account_shard(account_id));
+        let mut shard = write(self.shard(token));
+        if !filter(shard.peers.get(&token)?, shard.pending.get(&token).copied()) {
+            return None;
+        }
+        let peer = shard.remove(token)?;
+        if let Some(tokens) = accounts.get_mut(&account_id) {
+            tokens.retain(|t| *t != token);
+            if tokens.is_empty() {
+                accounts.remove(&account_id);
+            }
+        }
+        Some(peer)
+    }
+
+    /// 删除peer并更新索引
+    #[inline]
+    fn remove_peer(&self, token: u64) -> Option<Arc<T>> {
+        self.remove_peer_if(token, |_, _| true)
+    }
+
+    /// 删除此账号的所有peer
+    fn remove_account(&self, account_id: i32) -> Vec<Arc<T>> {
+        let tokens = write(self.account_shard(account_id))
+            .remove(&account_id)
+            .unwrap_or_default();
+        tokens
+            .into_iter()
+            .filter_map(|token| write(self.shard(token)).remove(token))
+            .collect()
+    }
+
+    /// 发送事件 没有订阅者时忽略
+    #[inline]
+    fn emit(&self, event: PeerEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// 运行清理回调
+    async fn on_clean(&self, peers: Vec<Arc<T>>) {
+        for peer in peers {
+            if let Err(err) = peer.on_clean().await {
+                log::error!("clean peer:{peer} error:{err}")
+            }
+            self.emit(PeerEvent::Cleaned {
+                token: peer.get_token(),
+                account_id: peer.get_account_id(),
+            });
+        }
+    }
+
+    ///新建PEER
+    /// 超过账号peer数量时按策略拒绝,或踢掉最早的peer
+    async fn create_peer(&self, account_id: i32) -> Result<u64> {
+        let settings = self.settings();
+        let token_generator = read(&self.token_generator).clone();
+        let metrics = self.metrics();
+
+        let (token, evicted) = {
+            let mut accounts = write(self.account_shard(account_id));
+            let tokens = accounts.entry(account_id).or_default();
+            let count = tokens.len();
+            let limited =
+                settings.max_peers_per_account > 0 && count >= settings.max_peers_per_account;
+            if limited && settings.session_limit_policy == SessionLimitPolicy::Reject {
+                bail!(
+                    "account id:{account_id} peers count:{count} limit:{}",
+                    settings.max_peers_per_account
+                )
+            }
+
+            let mut retry = 0;
+            let token = loop {
+                let token = match token_generator.generate(account_id) {
+                    Ok(token) => token,
+                    Err(err) => break Err(err),
+                };
+                let mut shard = write(self.shard(token));
+                if token != 0 && !shard.peers.contains_key(&token) {
+                    shard.insert(Arc::new(IPeer::create(token, account_id)));
+                    break Ok(token);
+                }
+                drop(shard);
+                retry += 1;
+                if retry >= CREATE_TOKEN_RETRY {
+                    break Err(anyhow!(
+                        "create token fail after {CREATE_TOKEN_RETRY} retries"
+                    ));
+                }
+                log::warn!("token:{token} exists, retry:{retry}");
+            };
+            let token = match token {
+                Ok(token) => token,
+                Err(err) => {
+                    if tokens.is_empty() {
+                        accounts.remove(&account_id);
+                    }
+                    return Err(err);
+                }
+            };
+
+            // 新token创建成功后才踢掉最早的peer
+            let mut evicted = Vec::new();
+            if limited {
+                for token in tokens.drain(..=count - settings.max_peers_per_account) {
+                    if let Some(peer) = write(self.shard(token)).remove(token) {
+                        evicted.push(peer);
+                    }
+                }
+            }
+            tokens.push(token);
+            (token, evicted)
+        };
+
+        self.kicked(evicted, KickReason::SessionLimit, String::new())
+            .await;
+
+        metrics.peers_created.inc();
+        self.emit(PeerEvent::Created { token, account_id });
         log::info!("create peer token:{}", token);
 
         Ok(token)
@@ -40,193 +389,413 @@ impl<T: IPeer + 'static> LinkPeerManager<T> {
     /// 获取peer
     #[inline]
     fn get_peer(&self, token: u64) -> Option<Arc<T>> {
-        self.peers.get(&token).cloned()
+        read(self.shard(token)).peers.get(&token).cloned()
     }
 
     /// 长连接携带token链接
-    /// 返回false表示token没找到 或者用户名对不上
-    #[inline]
-    fn peer_connect(&self, proxy_id: usize, account_id: i32, token: u64) -> Result<()> {
-        if let Some(peer) = self.peers.get(&token) {
-            if peer.get_account_id() == account_id {
-                peer.set_proxy_id(proxy_id);
-                peer.set_disconnect(false);
-                log::info!("peer token:{} connect", token);
-                Ok(())
-            } else {
-                bail!("account id:{account_id} not is token")
+    /// token没找到,用户名对不上,或已在其他代理连接且不允许抢占时返回错误
+    /// 重新连接时调用 on_reconnect,已在同一代理连接时不做处理
+    async fn peer_connect(&self, proxy_id: usize, account_id: i32, token: u64) -> Result<()> {
+        // 只有还没连接过的token检查过期,已连接过的peer过期后也能重连
+        let token_generator = read(&self.token_generator).clone();
+        token_generator.verify_sign(account_id, token)?;
+        let allow_takeover = self.settings().allow_connect_takeover;
+        let (peer, first, old_proxy_id, offline_duration) = {
+            let mut shard = write(self.shard(token));
+            let Some(peer) = shard.peers.get(&token).cloned() else {
+                bail!("account id:{account_id} not found")
+            };
+            ensure!(
+                peer.get_account_id() == account_id,
+                "account id:{account_id} not is token"
+            );
+            let old_proxy_id = peer.get_proxy_id();
+            if shard.pending.contains_key(&token) {
+                token_generator.verify(account_id, token)?;
+            }
+            let first = shard.pending.remove(&token).is_some();
+            let online = !first && !peer.is_disconnect();
+            if online && old_proxy_id == proxy_id {
+                // 同一代理重复连接 不算重新连接
+                return Ok(());
+            }
+            ensure!(
+                !online || old_proxy_id == proxy_id || allow_takeover,
+                "token:{token} already connected on proxy:{old_proxy_id}"
+            );
+            let offline_duration = shard
+                .offline
+                .remove(&token)
+                .map(|offline_time| {
+                    Duration::from_millis(((timestamp() - offline_time) / TICK).max(0) as u64)
+                })
+                .unwrap_or_default();
+            if old_proxy_id != proxy_id {
+                shard.remove_proxy_index(old_proxy_id, token);
+                shard.proxy_index.entry(proxy_id).or_default().insert(token);
+                if online {
+                    log::info!("peer token:{token} takeover from proxy:{old_proxy_id}");
+                    self.notify_kicked(old_proxy_id, token, KickReason::Takeover, String::new());
+                }
             }
+            peer.set_proxy_id(proxy_id);
+            peer.set_disconnect(false);
+            (peer, first, old_proxy_id, offline_duration)
+        };
+
+        log::info!("peer token:{} connect", token);
+        if first {
+            self.emit(PeerEvent::Connected {
+                token,
+                account_id,
+                proxy_id,
+            });
         } else {
-            bail!("account id:{account_id} not found")
+            self.emit(PeerEvent::Reconnected {
+                token,
+                account_id,
+                old_proxy_id,
+                proxy_id,
+            });
+            if let Err(err) = peer
+                .on_reconnect(old_proxy_id, proxy_id, offline_duration)
+                .await
+            {
+                log::error!("peer:{peer} On Reconnect err:{err}");
+            }
         }
+        Ok(())
     }
 
     /// 根据账号id 获取所有的peer
     /// 一个账号可对应多个peer
     #[inline]
     fn get_peer_by_account_id(&self, account_id: i32) -> Vec<Arc<T>> {
-        self.peers
-            .clone()
-            .values()
-            .filter_map(|peer| {
-                if peer.get_account_id() == account_id {
-                    Some(peer.clone())
-                } else {
-                    None
-                }
-            })
+        self.account_tokens(account_id)
+            .into_iter()
+            .filter_map(|token| self.get_peer(token))
             .collect()
     }
 
+    /// 获取所有peer
+    #[inline]
+    fn get_all_peer(&self) -> Vec<Arc<T>> {
+        self.shards
+            .iter()
+            .flat_map(|shard| read(shard).peers.values().cloned().collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// 获取peer数量
+    #[inline]
+    fn get_peer_count(&self) -> PeerCount {
+        self.shards
+            .iter()
+            .fold(PeerCount::default(), |mut count, shard| {
+                let shard = read(shard);
+                count.total += shard.peers.len();
+                // 还没有连接过的token不算已连接
+                count.connected += shard
+                    .peers
+                    .iter()
+                    .filter(|(token, p)| !p.is_disconnect() && !shard.pending.contains_key(token))
+                    .count();
+                count
+            })
+    }
+
+    /// 获取所有peer的账号id 已去重
+    #[inline]
+    fn get_account_ids(&self) -> Vec<i32> {
+        let mut account_ids = self
+            .accounts
+            .iter()
+            .flat_map(|accounts| read(accounts).keys().copied().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        account_ids.sort_unstable();
+        account_ids
+    }
+
     /// 获取此账号的所有token状态
     #[inline]
     fn get_token_state_by_account_id(&self, account_id: i32) -> Vec<GetTokenResult> {
         let now = timestamp();
-        self.peers
-            .values()
-            .filter_map(|peer| {
-                if peer.get_account_id() == account_id {
-                    Some(GetTokenResult {
-                        token: peer.get_token(),
-                        last_elapsed_time: peer.comparison_time(now),
-                        timeout: BASE_CONFIG.base.peer_clean_timeout_sec,
-                        is_wss_connect: !peer.is_disconnect(),
-                    })
+        let settings = self.settings();
+        self.account_tokens(account_id)
+            .into_iter()
+            .filter_map(|token| {
+                let shard = read(self.shard(token));
+                let peer = shard.peers.get(&token)?;
+                let (state, timeout) = if shard.pending.contains_key(&token) {
+                    (TokenState::Pending, settings.pending_connect_timeout_sec)
+                } else if peer.is_disconnect() {
+                    (TokenState::Disconnected, settings.peer_clean_timeout_sec)
                 } else {
-                    None
-                }
+                    (TokenState::Connected, settings.peer_clean_timeout_sec)
+                };
+                Some(GetTokenResult {
+                    token,
+                    last_elapsed_time: peer.comparison_time(now),
+                    timeout,
+                    is_wss_connect: state == TokenState::Connected,
+                    state,
+                })
             })
             .collect()
     }
 
+    /// 已从索引删除的peer 通知客户端被踢下线,设置断线并清理
+    async fn kicked(&self, peers: Vec<Arc<T>>, reason: KickReason, message: String) {
+        if peers.is_empty() {
+            return;
+        }
+        let metrics = self.metrics();
+        metrics
+            .peers_kicked
+            .get(reason.as_str())
+            .add(peers.len() as u64);
+        metrics.peers_cleaned.add(peers.len() as u64);
+        for peer in peers.iter() {
+            let token = peer.get_token();
+            log::info!("kick peer:{peer} token:{token} reason:{}", reason.as_str());
+            if !peer.is_disconnect() {
+                self.notify_kicked(peer.get_proxy_id(), token, reason, message.clone());
+                peer.set_disconnect(true);
+            }
+            self.emit(PeerEvent::Kicked {
+                token,
+                account_id: peer.get_account_id(),
+                reason,
+            });
+        }
+        self.on_clean(peers).await;
+    }
+
+    /// 踢下线 通知客户端,设置断线并清理
+    /// 返回false表示token没找到
+    #[inline]
+    async fn kick(&self, token: u64, reason: KickReason, message: String) -> bool {
+        let Some(peer) = self.remove_peer(token) else {
+            return false;
+        };
+        self.kicked(vec![peer], reason, message).await;
+        true
+    }
+
+    /// 踢掉此账号的所有peer 返回踢掉的数量
+    #[inline]
+    async fn kick_account(&self, account_id: i32, reason: KickReason, message: String) -> usize {
+        let peers = self.remove_account(account_id);
+        let count = peers.len();
+        self.kicked(peers, reason, message).await;
+        count
+    }
+
+    /// 通过peer所在代理通知客户端被踢下线
+    #[inline]
+    fn notify_kicked(&self, proxy_id: usize, token: u64, reason: KickReason, message: String) {
+        let Some(proxy) = read(&self.proxy).clone() else {
+            return;
+        };
+        if proxy_id == 0 {
+            return;
+        }
+        tokio::spawn(async move {
+            let data = match (Kicked {
+                token,
+                reason,
+                message,
+            })
+            .to(None)
+            {
+                Ok(data) => data,
+                Err(err) => {
+                    log::error!("serialize kicked error:{err}");
+                    return;
+                }
+            };
+            if let Some(netx_token) = proxy.get(proxy_id).await {
+                let proxy = impl_ref!(netx_token=>IProxy);
+                proxy.send_to_token(token, &data).await;
+            }
+        });
+    }
+
     /// 断线
+    /// peer已被其他代理抢占时 忽略旧代理的断线
     #[inline]
-    fn disconnect(&mut self, token: u64) {
-        if let Some(peer) = self.peers.get(&token) {
+    fn disconnect(&self, proxy_id: usize, token: u64) {
+        let (peer, connected) = {
+            let mut shard = write(self.shard(token));
+            let Some(peer) = shard.peers.get(&token).cloned() else {
+                log::warn!("disconnect peer not found:{}", token);
+                return;
+            };
+            if peer.get_proxy_id() != proxy_id {
+                log::debug!(
+                    "peer token:{token} on proxy:{} ignore disconnect from proxy:{proxy_id}",
+                    peer.get_proxy_id()
+                );
+                return;
+            }
+            let connected = !peer.is_disconnect();
+            if connected {
+                shard.offline.insert(token, timestamp());
+            }
             peer.set_disconnect(true);
-            log::debug!("peer token:{} disconnect", token);
-            let peer = peer.clone();
-            tokio::spawn(async move {
-                if let Err(err) = peer.on_disconnect().await {
-                    log::error!("peer:{peer} On Disconnect err:{err}");
-                }
+            (peer, connected)
+        };
+        if connected {
+            self.emit(PeerEvent::Disconnected {
+                token,
+                account_id: peer.get_account_id(),
+                proxy_id,
             });
-        } else {
-            log::warn!("disconnect peer not found:{}", token)
         }
+        log::debug!("peer token:{} disconnect", token);
+        tokio::spawn(async move {
+            if let Err(err) = peer.on_disconnect().await {
+                log::error!("peer:{peer} On Disconnect err:{err}");
+            }
+        });
     }
 
     /// 代理断线设置所有peer状态
     #[inline]
-    fn disconnect_for_proxy(&mut self, proxy_id: usize) {
+    fn disconnect_for_proxy(&self, proxy_id: usize) {
         let tokens = self
-            .peers
-            .values()
-            .filter_map(|p| {
-                if p.get_proxy_id() == proxy_id {
-                    Some(p.get_token())
-                } else {
-                    None
-                }
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                read(shard)
+                    .proxy_index
+                    .get(&proxy_id)
+                    .map(|tokens| tokens.iter().copied().collect::<Vec<_>>())
+                    .unwrap_or_default()
             })
             .collect::<Vec<_>>();
 
         for token in tokens {
-            self.disconnect(token);
+            self.disconnect(proxy_id, token);
         }
     }
 
     /// 删除指定玩家的peer
     #[inline]
-    async fn clean_by_account_id(&mut self, account_id: i32) {
-        let remove_list = self
-            .peers
-            .iter()
-            .filter_map(|(key, player)| {
-                if player.get_account_id() == account_id {
-                    Some(*key)
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
-
-        for remove_key in remove_list {
-            if let Some(peer) = self.peers.remove(&remove_key) {
-                if let Err(err) = peer.on_clean().await {
-                    log::error!("clean peer:{peer} token:{remove_key} error:{err} 2")
-                }
-            }
-        }
+    async fn clean_by_account_id(&self, account_id: i32) {
+        let peers = self.remove_account(account_id);
+        self.metrics().peers_cleaned.add(peers.len() as u64);
+        self.on_clean(peers).await;
     }
 
     /// 清理需要清理的peer
     #[inline]
-    async fn cleans(&mut self) -> Result<()> {
+    async fn cleans(&self) -> Result<()> {
         let now = timestamp();
-        let timeout = BASE_CONFIG.base.peer_clean_timeout_sec * SECOND * TICK;
-        // self.peers
-        //     .drain(|_, v| !v.is_disconnect() || v.comparison_time(now) < timeout);
+        let settings = self.settings();
+        let timeout = settings.peer_clean_timeout_sec * SECOND * TICK;
+        let pending_timeout = settings.pending_connect_timeout_sec * SECOND * TICK;
+        // 没连接过的按新建时间清理,其他的断线后按通信时间清理
+        let expired = |peer: &T, pending: Option<i64>| match pending {
+            Some(create_time) => now - create_time >= pending_timeout,
+            None => peer.is_disconnect() && peer.comparison_time(now) >= timeout,
+        };
 
         let cleans = self
-            .peers
+            .shards
             .iter()
-            .filter_map(|(k, v)| {
-                if v.is_disconnect() && v.comparison_time(now) >= timeout {
-                    Some(*k)
-                } else {
-                    None
-                }
+            .flat_map(|shard| {
+                let shard = read(shard);
+                shard
+                    .peers
+                    .iter()
+                    .filter_map(|(token, peer)| {
+                        expired(peer, shard.pending.get(token).copied()).then_some(*token)
+                    })
+                    .collect::<Vec<_>>()
             })
             .collect::<Vec<_>>();
 
+        // 选出后可能已重连 删除时再检查一次
         let clean_peers = cleans
             .into_iter()
-            .filter_map(|k| self.peers.remove(&k))
+            .filter_map(|token| self.remove_peer_if(token, expired))
             .collect::<Vec<_>>();
 
-        for peer in clean_peers {
-            if let Err(err) = peer.on_clean().await {
-                log::error!("clean peer:{} error:{err}", peer)
-            }
-        }
-
+        self.metrics().peers_cleaned.add(clean_peers.len() as u64);
+        self.on_clean(clean_peers).await;
         Ok(())
     }
 
     /// 清理所有peer
     #[inline]
-    async fn clear_all(&mut self) {
-        let keys = self.peers.keys().cloned().collect::<Vec<_>>();
-        let clean_peers = keys
-            .into_iter()
-            .filter_map(|k| self.peers.remove(&k))
-            .collect::<Vec<_>>();
-        for peer in clean_peers {
-            if let Err(err) = peer.on_clean().await {
-                log::error!("clear all peer:{} error:{err}", peer)
+    async fn clear_all(&self) {
+        let mut clean_peers = Vec::new();
+        for accounts in self.accounts.iter() {
+            let mut accounts = write(accounts);
+            for token in accounts.drain().flat_map(|(_, tokens)| tokens) {
+                if let Some(peer) = write(self.shard(token)).remove(token) {
+                    clean_peers.push(peer);
+                }
             }
         }
+        self.metrics().peers_cleaned.add(clean_peers.len() as u64);
+        self.on_clean(clean_peers).await;
     }
 }
 
 #[async_trait::async_trait]
 pub trait ILinkPeerManager: Send + Sync {
+    /// 设置基本配置
+    async fn set_base_config(&self, config: &BaseConfig);
+    /// 设置指标
+    async fn set_metrics(&self, metrics: Arc<Metrics>);
+    /// 设置token生成器
+    async fn set_token_generator(&self, token_generator: Arc<dyn TokenGenerator>);
+    /// 设置代理管理器 用于通知客户端
+    async fn set_proxy(&self, proxy: Arc<RwModel<ProxyService>>);
+    /// 订阅peer生命周期事件
+    fn subscribe(&self) -> broadcast::Receiver<PeerEvent>;
+    /// 设置扩展数据 按值的类型覆盖
+    /// 返回false表示token没找到
+    fn set_extension_any(&self, token: u64, value: Extension) -> bool;
+    /// 获取扩展数据
+    fn get_extension_any(&self, token: u64, type_id: TypeId) -> Option<Extension>;
+    /// 获取扩展数据 没有时用init新建
+    /// token没找到返回None
+    fn get_or_insert_extension_any(
+        &self,
+        token: u64,
+        type_id: TypeId,
+        init: &mut dyn FnMut() -> Extension,
+    ) -> Option<Extension>;
+    /// 删除扩展数据
+    fn remove_extension_any(&self, token: u64, type_id: TypeId) -> Option<Extension>;
     /// 新建PEER
     async fn create_peer(&self, account_id: i32) -> Result<u64>;
     /// 长连接携带token链接
-    /// 返回false表示token没找到
+    /// token没找到,首次连接时token已过期,或已在其他代理连接且不允许抢占时返回错误
     async fn connect_token(&self, proxy_id: usize, account_id: i32, token: u64) -> Result<()>;
     /// 获取此账号的所有token状态
     async fn get_token_state_by_account_id(&self, account_id: i32) -> Vec<GetTokenResult>;
-    /// 断线
-    async fn disconnect_token(&self, token: u64);
+    /// 获取所有peer的账号id
+    async fn get_account_ids(&self) -> Vec<i32>;
+    /// 获取peer数量
+    async fn get_peer_count(&self) -> PeerCount;
+    /// 代理通知peer断线
+    /// peer已在其他代理连接时忽略
+    async fn disconnect_token(&self, proxy_id: usize, token: u64);
     /// 清理需要清理的peer
     async fn cleans(&self) -> Result<()>;
     /// 从网关断线所有peer
     async fn disconnect_for_proxy(&self, proxy_id: usize);
     /// 清理指定account id的账号
     async fn clean_by_account_id(&self, account_id: i32);
+    /// 踢下线 通知客户端后设置断线并清理
+    /// 返回false表示token没找到
+    async fn kick_token(&self, token: u64, reason: KickReason, message: String) -> bool;
+    /// 踢掉此账号的所有peer 返回踢掉的数量
+    async fn kick_account(&self, account_id: i32, reason: KickReason, message: String) -> usize;
     /// 清理所有token
     async fn clear_all(&self);
 }
@@ -242,74 +811,513 @@ pub trait ILinkPeerManagerPeer<T>: ILinkPeerManager {
 }
 
 #[async_trait::async_trait]
-impl<T: IPeer + 'static> ILinkPeerManager for Actor<LinkPeerManager<T>> {
+impl<T: IPeer + 'static> ILinkPeerManager for LinkPeerManager<T> {
+    #[inline]
+    async fn set_base_config(&self, config: &BaseConfig) {
+        *write(&self.settings) = Settings {
+            peer_clean_timeout_sec: config.peer_clean_timeout_sec,
+            pending_connect_timeout_sec: config.pending_connect_timeout_sec,
+            max_peers_per_account: config.max_peers_per_account,
+            session_limit_policy: config.session_limit_policy,
+            allow_connect_takeover: config.allow_connect_takeover,
+        };
+    }
+
+    #[inline]
+    async fn set_metrics(&self, metrics: Arc<Metrics>) {
+        *write(&self.metrics) = metrics;
+    }
+
+    #[inline]
+    async fn set_token_generator(&self, token_generator: Arc<dyn TokenGenerator>) {
+        *write(&self.token_generator) = token_generator;
+    }
+
+    #[inline]
+    async fn set_proxy(&self, proxy: Arc<RwModel<ProxyService>>) {
+        *write(&self.proxy) = Some(proxy);
+    }
+
+    #[inline]
+    fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.events.subscribe()
+    }
+
+    #[inline]
+    fn set_extension_any(&self, token: u64, value: Extension) -> bool {
+        let mut shard = write(self.shard(token));
+        if !shard.peers.contains_key(&token) {
+            return false;
+        }
+        shard
+            .extensions
+            .entry(token)
+            .or_default()
+            .insert((*value).type_id(), value);
+        true
+    }
+
+    #[inline]
+    fn get_extension_any(&self, token: u64, type_id: TypeId) -> Option<Extension> {
+        read(self.shard(token))
+            .extensions
+            .get(&token)?
+            .get(&type_id)
+            .cloned()
+    }
+
+    #[inline]
+    fn get_or_insert_extension_any(
+        &self,
+        token: u64,
+        type_id: TypeId,
+        init: &mut dyn FnMut() -> Extension,
+    ) -> Option<Extension> {
+        if let Some(value) = self.get_extension_any(token, type_id) {
+            return Some(value);
+        }
+        if !read(self.shard(token)).peers.contains_key(&token) {
+            return None;
+        }
+        // init 可能调用管理器,不能在持有锁时执行
+        let value = init();
+        let mut shard = write(self.shard(token));
+        if !shard.peers.contains_key(&token) {
+            return None;
+        }
+        Some(
+            shard
+                .extensions
+                .entry(token)
+                .or_default()
+                .entry(type_id)
+                .or_insert(value)
+                .clone(),
+        )
+    }
+
+    #[inline]
+    fn remove_extension_any(&self, token: u64, type_id: TypeId) -> Option<Extension> {
+        let mut shard = write(self.shard(token));
+        let extensions = shard.extensions.get_mut(&token)?;
+        let value = extensions.remove(&type_id);
+        if extensions.is_empty() {
+            shard.extensions.remove(&token);
+        }
+        value
+    }
+
     #[inline]
     async fn create_peer(&self, account_id: i32) -> Result<u64> {
-        self.inner_call(|inner| async move { inner.get_mut().create_peer(account_id) })
-            .await
+        LinkPeerManager::create_peer(self, account_id).await
     }
 
     #[inline]
     async fn connect_token(&self, proxy_id: usize, account_id: i32, token: u64) -> Result<()> {
-        self.inner_call(
-            |inner| async move { inner.get_mut().peer_connect(proxy_id, account_id, token) },
-        )
-        .await
+        self.peer_connect(proxy_id, account_id, token).await
     }
 
     #[inline]
     async fn get_token_state_by_account_id(&self, account_id: i32) -> Vec<GetTokenResult> {
-        self.inner_call(
-            |inner| async move { inner.get_mut().get_token_state_by_account_id(account_id) },
-        )
-        .await
+        LinkPeerManager::get_token_state_by_account_id(self, account_id)
+    }
+
+    #[inline]
+    async fn get_account_ids(&self) -> Vec<i32> {
+        LinkPeerManager::get_account_ids(self)
+    }
+
+    #[inline]
+    async fn get_peer_count(&self) -> PeerCount {
+        LinkPeerManager::get_peer_count(self)
     }
 
     #[inline]
-    async fn disconnect_token(&self, token: u64) {
-        self.inner_call(|inner| async move { inner.get_mut().disconnect(token) })
-            .await
+    async fn disconnect_token(&self, proxy_id: usize, token: u64) {
+        self.disconnect(proxy_id, token)
     }
 
     #[inline]
     async fn cleans(&self) -> Result<()> {
-        self.inner_call(|inner| async move { inner.get_mut().cleans().await })
-            .await
+        LinkPeerManager::cleans(self).await
     }
 
     #[inline]
     async fn disconnect_for_proxy(&self, proxy_id: usize) {
-        self.inner_call(|inner| async move { inner.get_mut().disconnect_for_proxy(proxy_id) })
-            .await
+        LinkPeerManager::disconnect_for_proxy(self, proxy_id)
     }
 
     #[inline]
     async fn clean_by_account_id(&self, account_id: i32) {
-        self.inner_call(
-            |inner| async move { inner.get_mut().clean_by_account_id(account_id).await },
-        )
-        .await
+        LinkPeerManager::clean_by_account_id(self, account_id).await
     }
+
+    #[inline]
+    async fn kick_token(&self, token: u64, reason: KickReason, message: String) -> bool {
+        self.kick(token, reason, message).await
+    }
+
+    #[inline]
+    async fn kick_account(&self, account_id: i32, reason: KickReason, message: String) -> usize {
+        LinkPeerManager::kick_account(self, account_id, reason, message).await
+    }
+
     #[inline]
     async fn clear_all(&self) {
-        self.inner_call(|inner| async move { inner.get_mut().clear_all().await })
-            .await
+        LinkPeerManager::clear_all(self).await
     }
 }
 
-impl<T: IPeer + 'static> ILinkPeerManagerPeer<T> for Actor<LinkPeerManager<T>> {
+impl<T: IPeer + 'static> ILinkPeerManagerPeer<T> for LinkPeerManager<T> {
     #[inline]
     fn get_peer(&self, token: u64) -> Option<Arc<T>> {
-        unsafe { self.deref_inner().get_peer(token) }
+        LinkPeerManager::get_peer(self, token)
     }
 
     #[inline]
     fn get_peer_by_account_id(&self, account_id: i32) -> Vec<Arc<T>> {
-        unsafe { self.deref_inner().get_peer_by_account_id(account_id) }
+        LinkPeerManager::get_peer_by_account_id(self, account_id)
     }
 
     #[inline]
     fn get_all_peer(&self) -> Vec<Arc<T>> {
-        unsafe { self.deref_inner().peers.values().cloned().collect() }
+        LinkPeerManager::get_all_peer(self)
+    }
+}
+
+/// 按类型读写peer扩展数据
+/// 每种类型每个token保存一个值,peer清理时释放
+/// ``` ignore
+/// struct RateLimit(AtomicU32);
+/// let limit = game.peers.get_or_insert_extension(token, || RateLimit(AtomicU32::new(0)));
+/// ```
+pub trait PeerExtensions {
+    /// 设置扩展数据 返回false表示token没找到
+    fn set_extension<V: Any + Send + Sync>(&self, token: u64, value: V) -> bool;
+    /// 获取扩展数据
+    fn get_extension<V: Any + Send + Sync>(&self, token: u64) -> Option<Arc<V>>;
+    /// 获取扩展数据 没有时用init新建 token没找到返回None
+    fn get_or_insert_extension<V: Any + Send + Sync>(
+        &self,
+        token: u64,
+        init: impl FnOnce() -> V,
+    ) -> Option<Arc<V>>;
+    /// 删除扩展数据
+    fn remove_extension<V: Any + Send + Sync>(&self, token: u64) -> Option<Arc<V>>;
+}
+
+impl<M: ILinkPeerManager + ?Sized> PeerExtensions for M {
+    #[inline]
+    fn set_extension<V: Any + Send + Sync>(&self, token: u64, value: V) -> bool {
+        self.set_extension_any(token, Arc::new(value))
+    }
+
+    #[inline]
+    fn get_extension<V: Any + Send + Sync>(&self, token: u64) -> Option<Arc<V>> {
+        self.get_extension_any(token, TypeId::of::<V>())?
+            .downcast()
+            .ok()
+    }
+
+    #[inline]
+    fn get_or_insert_extension<V: Any + Send + Sync>(
+        &self,
+        token: u64,
+        init: impl FnOnce() -> V,
+    ) -> Option<Arc<V>> {
+        let mut init = Some(init);
+        self.get_or_insert_extension_any(token, TypeId::of::<V>(), &mut || {
+            Arc::new(init.take().expect("extension init called once")())
+        })?
+        .downcast()
+        .ok()
+    }
+
+    #[inline]
+    fn remove_extension<V: Any + Send + Sync>(&self, token: u64) -> Option<Arc<V>> {
+        self.remove_extension_any(token, TypeId::of::<V>())?
+            .downcast()
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::peer::{BasicPeer, PeerState};
+    use crate::services::HmacTokenGenerator;
+
+    struct TestState;
+
+    impl PeerState for TestState {
+        fn create(_token: u64, _account_id: i32) -> Self {
+            TestState
+        }
+    }
+
+    type Peers = LinkPeerManager<BasicPeer<TestState>>;
+
+    fn peers(max_peers_per_account: usize, session_limit_policy: SessionLimitPolicy) -> Peers {
+        let peers = Peers::default();
+        *write(&peers.settings) = Settings {
+            peer_clean_timeout_sec: 300,
+            pending_connect_timeout_sec: 60,
+            max_peers_per_account,
+            session_limit_policy,
+            allow_connect_takeover: false,
+        };
+        peers
+    }
+
+    fn events(rx: &mut broadcast::Receiver<PeerEvent>) -> Vec<PeerEvent> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    fn proxy_tokens(peers: &Peers, proxy_id: usize) -> Vec<u64> {
+        peers
+            .shards
+            .iter()
+            .flat_map(|shard| {
+                read(shard)
+                    .proxy_index
+                    .get(&proxy_id)
+                    .map(|tokens| tokens.iter().copied().collect::<Vec<_>>())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn create_connect_disconnect_clean() {
+        let clock = TestClock::set().await;
+        let peers = peers(0, SessionLimitPolicy::Reject);
+        let mut rx = peers.subscribe();
+
+        let token = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        let count = peers.get_peer_count();
+        assert_eq!((count.total, count.connected), (1, 0));
+        peers.connect_token(1, 1, token).await.unwrap();
+        let count = peers.get_peer_count();
+        assert_eq!((count.total, count.connected), (1, 1));
+
+        clock.advance(Duration::from_secs(10));
+        peers.disconnect(1, token);
+        assert_eq!(peers.get_peer_count().connected, 0);
+
+        clock.advance(Duration::from_secs(299));
+        peers.cleans().await.unwrap();
+        assert!(peers.get_peer(token).is_some());
+
+        clock.advance(Duration::from_secs(1));
+        peers.cleans().await.unwrap();
+        assert!(peers.get_peer(token).is_none());
+        assert!(peers.get_account_ids().is_empty());
+
+        let events = events(&mut rx);
+        assert!(matches!(
+            events[..],
+            [
+                PeerEvent::Created { .. },
+                PeerEvent::Connected { proxy_id: 1, .. },
+                PeerEvent::Disconnected { proxy_id: 1, .. },
+                PeerEvent::Cleaned { .. },
+            ]
+        ));
+        assert!(events.iter().all(|event| event.token() == token));
+    }
+
+    #[tokio::test]
+    async fn pending_timeout() {
+        let clock = TestClock::set().await;
+        let peers = peers(0, SessionLimitPolicy::Reject);
+
+        let pending = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        let connected = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        peers.connect_token(1, 1, connected).await.unwrap();
+
+        clock.advance(Duration::from_secs(59));
+        peers.cleans().await.unwrap();
+        assert!(peers.get_peer(pending).is_some());
+
+        clock.advance(Duration::from_secs(1));
+        peers.cleans().await.unwrap();
+        assert!(peers.get_peer(pending).is_none());
+        assert!(peers.get_peer(connected).is_some());
+        assert_eq!(peers.account_tokens(1), vec![connected]);
+        assert!(peers.connect_token(1, 1, pending).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn evict_oldest() {
+        let _clock = TestClock::set().await;
+        let peers = peers(2, SessionLimitPolicy::EvictOldest);
+        let mut rx = peers.subscribe();
+
+        let mut tokens = Vec::new();
+        for _ in 0..4 {
+            tokens.push(ILinkPeerManager::create_peer(&peers, 1).await.unwrap());
+        }
+        assert_eq!(peers.account_tokens(1), tokens[2..]);
+        assert!(peers.get_peer(tokens[0]).is_none());
+        assert!(peers.get_peer(tokens[1]).is_none());
+
+        let kicked = events(&mut rx)
+            .into_iter()
+            .filter_map(|event| match event {
+                PeerEvent::Kicked {
+                    token,
+                    reason: KickReason::SessionLimit,
+                    ..
+                } => Some(token),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kicked, tokens[..2]);
+
+        // 新token创建失败时不踢掉已有的peer
+        struct FailGenerator;
+        impl TokenGenerator for FailGenerator {
+            fn generate(&self, _account_id: i32) -> Result<u64> {
+                bail!("generate fail")
+            }
+        }
+        peers.set_token_generator(Arc::new(FailGenerator)).await;
+        assert!(ILinkPeerManager::create_peer(&peers, 1).await.is_err());
+        assert_eq!(peers.account_tokens(1), tokens[2..]);
+        assert!(events(&mut rx).is_empty());
+        assert!(ILinkPeerManager::create_peer(&peers, 2).await.is_err());
+        assert!(peers.account_tokens(2).is_empty());
+        assert_eq!(peers.get_account_ids(), vec![1]);
+
+        let peers = self::peers(1, SessionLimitPolicy::Reject);
+        let token = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        assert!(ILinkPeerManager::create_peer(&peers, 1).await.is_err());
+        assert_eq!(peers.account_tokens(1), vec![token]);
+    }
+
+    #[tokio::test]
+    async fn index_after_remove() {
+        let _clock = TestClock::set().await;
+        let peers = peers(0, SessionLimitPolicy::Reject);
+
+        let a1 = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        let a2 = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        let b = ILinkPeerManager::create_peer(&peers, 2).await.unwrap();
+        let c = ILinkPeerManager::create_peer(&peers, 3).await.unwrap();
+        peers.connect_token(1, 1, a1).await.unwrap();
+        peers.connect_token(2, 1, a2).await.unwrap();
+        peers.connect_token(1, 2, b).await.unwrap();
+        peers.connect_token(2, 3, c).await.unwrap();
+        assert_eq!(peers.get_account_ids(), vec![1, 2, 3]);
+
+        assert!(peers.kick(a1, KickReason::Admin, String::new()).await);
+        let tokens = peers
+            .get_peer_by_account_id(1)
+            .iter()
+            .map(|peer| peer.get_token())
+            .collect::<Vec<_>>();
+        assert_eq!(tokens, vec![a2]);
+        assert_eq!(proxy_tokens(&peers, 1), vec![b]);
+
+        peers.clean_by_account_id(3).await;
+        assert_eq!(peers.get_account_ids(), vec![1, 2]);
+        assert!(peers.get_peer_by_account_id(3).is_empty());
+        assert_eq!(proxy_tokens(&peers, 2), vec![a2]);
+
+        peers.disconnect_for_proxy(1);
+        assert!(peers.get_peer(b).unwrap().is_disconnect());
+        assert!(!peers.get_peer(a2).unwrap().is_disconnect());
+
+        peers.clear_all().await;
+        assert!(peers.get_account_ids().is_empty());
+        assert!(peers.get_all_peer().is_empty());
+        assert!(proxy_tokens(&peers, 2).is_empty());
+    }
+
+    #[tokio::test]
+    async fn extension_init_reentrant() {
+        let _clock = TestClock::set().await;
+        let peers = peers(0, SessionLimitPolicy::Reject);
+        let token = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+
+        // init 里访问同一个管理器不能死锁
+        let value = peers
+            .get_or_insert_extension(token, || {
+                assert!(peers.get_extension::<u32>(token).is_none());
+                peers.set_extension(token, 7u64);
+                1u32
+            })
+            .unwrap();
+        assert_eq!(*value, 1);
+        assert_eq!(peers.get_extension::<u64>(token).as_deref(), Some(&7));
+        let value = peers
+            .get_or_insert_extension(token, || unreachable!() as u32)
+            .unwrap();
+        assert_eq!(*value, 1);
+
+        assert_eq!(peers.remove_extension::<u32>(token).as_deref(), Some(&1));
+        assert!(peers.get_or_insert_extension(0, || 1u32).is_none());
+    }
+
+    #[tokio::test]
+    async fn takeover_then_stale_disconnect() {
+        let _clock = TestClock::set().await;
+        let peers = peers(0, SessionLimitPolicy::Reject);
+        write(&peers.settings).allow_connect_takeover = true;
+        let token = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        peers.connect_token(1, 1, token).await.unwrap();
+        let mut rx = peers.subscribe();
+
+        // 同一代理重复连接 不产生事件
+        peers.connect_token(1, 1, token).await.unwrap();
+        assert!(events(&mut rx).is_empty());
+
+        peers.connect_token(2, 1, token).await.unwrap();
+        assert!(matches!(
+            events(&mut rx)[..],
+            [PeerEvent::Reconnected {
+                old_proxy_id: 1,
+                proxy_id: 2,
+                ..
+            }]
+        ));
+
+        // 旧代理之后才通知断线
+        peers.disconnect_token(1, token).await;
+        let peer = peers.get_peer(token).unwrap();
+        assert!(!peer.is_disconnect());
+        assert_eq!(peer.get_proxy_id(), 2);
+        assert!(events(&mut rx).is_empty());
+        peers.disconnect_for_proxy(1);
+        assert!(!peer.is_disconnect());
+
+        peers.disconnect_token(2, token).await;
+        assert!(peer.is_disconnect());
+        assert!(matches!(
+            events(&mut rx)[..],
+            [PeerEvent::Disconnected { proxy_id: 2, .. }]
+        ));
+    }
+
+    #[tokio::test]
+    async fn token_expire_on_first_connect() {
+        let clock = TestClock::set().await;
+        let peers = peers(0, SessionLimitPolicy::Reject);
+        write(&peers.settings).pending_connect_timeout_sec = 3600;
+        peers
+            .set_token_generator(Arc::new(HmacTokenGenerator::new(1, b"secret", 60).unwrap()))
+            .await;
+        let pending = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        let connected = ILinkPeerManager::create_peer(&peers, 1).await.unwrap();
+        peers.connect_token(1, 1, connected).await.unwrap();
+        peers.disconnect_token(1, connected).await;
+
+        clock.advance(Duration::from_secs(180));
+        let err = peers.connect_token(1, 1, pending).await.unwrap_err();
+        assert!(err.to_string().contains("expire"), "{err}");
+        assert!(peers.get_peer(pending).is_some());
+        // 已连接过的token过期后可以重连
+        peers.connect_token(1, 1, connected).await.unwrap();
     }
 }
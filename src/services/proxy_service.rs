@@ -1,22 +1,30 @@
 use crate::controller::ProxyController;
 use anyhow::{bail, Result};
 use aqueue::RwModel;
+use netxserver::prelude::tcpserver::IPeer;
 use netxserver::prelude::*;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 /// 代理服务器查询器
 #[derive(Default)]
 pub struct ProxyService {
     proxy_map: HashMap<usize, i64>,
-    manager: Option<Arc<dyn ITokenManager<ProxyController>>>,
+    /// 管理器由 NetXServer 持有,这里只保存弱引用
+    /// 避免 Game -> ProxyService -> 管理器 -> ProxyController -> Game 循环引用
+    manager: Option<Weak<dyn ITokenManager<ProxyController>>>,
 }
 
 impl ProxyService {
     /// 设置 netx token管理器
     #[inline]
     fn set_manager(&mut self, manager: Arc<dyn ITokenManager<ProxyController>>) {
-        self.manager = Some(manager)
+        self.manager = Some(Arc::downgrade(&manager))
+    }
+
+    #[inline]
+    fn manager(&self) -> Option<Arc<dyn ITokenManager<ProxyController>>> {
+        self.manager.as_ref()?.upgrade()
     }
 
     #[inline]
@@ -29,10 +37,15 @@ impl ProxyService {
         self.proxy_map.remove(&proxy_id);
     }
 
+    #[inline]
+    fn count(&self) -> usize {
+        self.proxy_map.len()
+    }
+
     #[inline]
     async fn get(&self, proxy_id: usize) -> Option<NetxToken<ProxyController>> {
         if let Some(session_id) = self.proxy_map.get(&proxy_id) {
-            if let Some(ref manager) = self.manager {
+            if let Some(manager) = self.manager() {
                 manager.get_token(*session_id).await
             } else {
                 None
@@ -42,9 +55,28 @@ impl ProxyService {
         }
     }
 
+    /// 断开所有代理连接
+    #[inline]
+    async fn disconnect_all(&mut self) -> Result<()> {
+        let tokens = self.get_all().await?;
+        self.proxy_map.clear();
+        for token in tokens {
+            if let Some(peer) = token.get_peer().await.and_then(|weak| weak.upgrade()) {
+                if let Err(err) = peer.disconnect().await {
+                    log::error!(
+                        "disconnect proxy session:{} error:{err}",
+                        token.get_session_id()
+                    );
+                }
+            }
+        }
+        self.manager = None;
+        Ok(())
+    }
+
     #[inline]
     async fn get_all(&self) -> Result<Vec<NetxToken<ProxyController>>> {
-        if let Some(ref manager) = self.manager {
+        if let Some(manager) = self.manager() {
             manager.get_all_tokens().await
         } else {
             bail!("manager is none")
@@ -60,10 +92,14 @@ pub trait IProxyService {
     async fn add(&self, proxy_id: usize, session_id: i64);
     /// 删除代理服务器
     async fn remove(&self, proxy_id: usize);
+    /// 已注册的代理数量
+    async fn count(&self) -> usize;
     /// 查询代理服务器session id
     async fn get(&self, proxy_id: usize) -> Option<NetxToken<ProxyController>>;
     /// 获取所有代理
     async fn get_all_token(&self) -> Result<Vec<NetxToken<ProxyController>>>;
+    /// 断开所有代理连接
+    async fn disconnect_all(&self) -> Result<()>;
 }
 #[async_trait::async_trait]
 impl IProxyService for RwModel<ProxyService> {
@@ -83,6 +119,11 @@ impl IProxyService for RwModel<ProxyService> {
         self.call_mut(|mut inner| async move { inner.remove(proxy_id) })
             .await
     }
+    #[inline]
+    async fn count(&self) -> usize {
+        self.call(|inner| async move { inner.count() }).await
+    }
+
     #[inline]
     async fn get(&self, proxy_id: usize) -> Option<NetxToken<ProxyController>> {
         self.call(|inner| async move { inner.get(proxy_id).await })
@@ -94,4 +135,10 @@ impl IProxyService for RwModel<ProxyService> {
         self.call(|inner| async move { inner.get_all().await })
             .await
     }
+
+    #[inline]
+    async fn disconnect_all(&self) -> Result<()> {
+        self.call_mut(|mut inner| async move { inner.disconnect_all().await })
+            .await
+    }
 }
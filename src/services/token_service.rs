@@ -0,0 +1,213 @@
+use anyhow::{anyhow, ensure, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::clock;
+
+/// token的位数,兼容 JavaScript 数字精度
+const TOKEN_BITS: u32 = 53;
+
+/// 签名token中过期时间(分钟)的位数
+const EXPIRE_BITS: u32 = 20;
+
+/// 签名token中随机数的位数
+const NONCE_BITS: u32 = 6;
+
+/// 签名token中签名的位数
+const SIGN_BITS: u32 = TOKEN_BITS - EXPIRE_BITS - NONCE_BITS;
+
+/// token 生成器
+pub trait TokenGenerator: Send + Sync {
+    /// 为账号生成token
+    fn generate(&self, account_id: i32) -> Result<u64>;
+    /// 验证token签名是否属于此账号 不检查过期
+    /// 不能验证的生成器返回Ok
+    fn verify_sign(&self, _account_id: i32, _token: u64) -> Result<()> {
+        Ok(())
+    }
+    /// 验证token是否属于此账号且未过期
+    fn verify(&self, account_id: i32, token: u64) -> Result<()> {
+        self.verify_sign(account_id, token)
+    }
+}
+
+/// 使用系统安全随机数生成token
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RandomTokenGenerator;
+
+impl TokenGenerator for RandomTokenGenerator {
+    #[inline]
+    fn generate(&self, _account_id: i32) -> Result<u64> {
+        let token = getrandom::u64().map_err(|err| anyhow!("generate random token error:{err}"))?;
+        Ok(token & ((1 << TOKEN_BITS) - 1))
+    }
+}
+
+/// HMAC-SHA256 签名的token
+/// 共53位,兼容 JavaScript 数字精度
+/// 格式为 高20位过期时间(分钟) 6位随机数 低27位签名
+/// 签名包含 server_id account_id,不用查表即可验证token是否有效
+pub struct HmacTokenGenerator {
+    server_id: u32,
+    mac: Hmac<Sha256>,
+    /// 有效期(分钟)
+    expire_minute: u64,
+}
+
+impl HmacTokenGenerator {
+    /// 新建签名生成器 有效期最长约一年
+    pub fn new(server_id: u32, secret: &[u8], expire_sec: u64) -> Result<Self> {
+        ensure!(!secret.is_empty(), "token secret must not be empty");
+        let expire_minute = expire_sec.div_ceil(60).max(1);
+        ensure!(
+            expire_minute < 1 << (EXPIRE_BITS - 1),
+            "token expire_sec:{expire_sec} too long"
+        );
+        Ok(Self {
+            server_id,
+            mac: Hmac::new_from_slice(secret).map_err(|err| anyhow!("token secret error:{err}"))?,
+            expire_minute,
+        })
+    }
+
+    /// 当前分钟数
+    #[inline]
+    fn now_minute() -> u64 {
+        clock::now().timestamp() as u64 / 60
+    }
+
+    /// 截断的签名
+    #[inline]
+    fn sign(&self, account_id: i32, head: u64) -> u64 {
+        let mut mac = self.mac.clone();
+        mac.update(&self.server_id.to_le_bytes());
+        mac.update(&account_id.to_le_bytes());
+        mac.update(&head.to_le_bytes());
+        let sign = mac.finalize().into_bytes();
+        (u32::from_be_bytes([sign[0], sign[1], sign[2], sign[3]]) >> (32 - SIGN_BITS)) as u64
+    }
+
+    #[inline]
+    fn generate_at(&self, account_id: i32, now_minute: u64, nonce: u64) -> u64 {
+        let expire = (now_minute + self.expire_minute) & ((1 << EXPIRE_BITS) - 1);
+        let head = (expire << NONCE_BITS) | (nonce & ((1 << NONCE_BITS) - 1));
+        (head << SIGN_BITS) | self.sign(account_id, head)
+    }
+
+    #[inline]
+    fn verify_expire_at(&self, token: u64, now_minute: u64) -> Result<()> {
+        // 按20位回绕比较,剩余时间超过一半视为已过期
+        let expire = token >> (SIGN_BITS + NONCE_BITS);
+        let remain = expire.wrapping_sub(now_minute) & ((1 << EXPIRE_BITS) - 1);
+        ensure!(remain < 1 << (EXPIRE_BITS - 1), "token:{token} expired");
+        Ok(())
+    }
+}
+
+impl TokenGenerator for HmacTokenGenerator {
+    fn generate(&self, account_id: i32) -> Result<u64> {
+        let nonce =
+            getrandom::u32().map_err(|err| anyhow!("generate token nonce error:{err}"))? as u64;
+        Ok(self.generate_at(account_id, Self::now_minute(), nonce))
+    }
+
+    fn verify_sign(&self, account_id: i32, token: u64) -> Result<()> {
+        ensure!(token >> TOKEN_BITS == 0, "token:{token} out of range");
+        let head = token >> SIGN_BITS;
+        ensure!(
+            self.sign(account_id, head) == token & ((1 << SIGN_BITS) - 1),
+            "token:{token} sign error"
+        );
+        Ok(())
+    }
+
+    fn verify(&self, account_id: i32, token: u64) -> Result<()> {
+        self.verify_sign(account_id, token)?;
+        self.verify_expire_at(token, Self::now_minute())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generator(expire_sec: u64) -> HmacTokenGenerator {
+        HmacTokenGenerator::new(10251, b"secret", expire_sec).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let generator = generator(3600);
+        for account_id in [1, -1, i32::MAX] {
+            let token = generator.generate(account_id).unwrap();
+            assert!(token < 1 << TOKEN_BITS);
+            generator.verify(account_id, token).unwrap();
+        }
+        let token = RandomTokenGenerator.generate(1).unwrap();
+        assert!(token < 1 << TOKEN_BITS);
+    }
+
+    #[test]
+    fn tamper() {
+        let generator = generator(3600);
+        let token = generator.generate_at(7, 1000, 3);
+        generator.verify_sign(7, token).unwrap();
+        // 其他账号
+        assert!(generator.verify_sign(8, token).is_err());
+        // 改任意一位
+        for bit in 0..TOKEN_BITS {
+            assert!(
+                generator.verify_sign(7, token ^ (1 << bit)).is_err(),
+                "{bit}"
+            );
+        }
+        assert!(generator.verify_sign(7, token | (1 << TOKEN_BITS)).is_err());
+        // 其他 server_id 或 secret
+        let other = HmacTokenGenerator::new(10252, b"secret", 3600).unwrap();
+        assert!(other.verify_sign(7, token).is_err());
+        let other = HmacTokenGenerator::new(10251, b"other", 3600).unwrap();
+        assert!(other.verify_sign(7, token).is_err());
+    }
+
+    #[test]
+    fn expire() {
+        let generator = generator(3600);
+        let token = generator.generate_at(7, 1000, 0);
+        generator.verify_expire_at(token, 1000).unwrap();
+        generator.verify_expire_at(token, 1059).unwrap();
+        generator.verify_expire_at(token, 1060).unwrap();
+        assert!(generator.verify_expire_at(token, 1061).is_err());
+        assert!(generator.verify_expire_at(token, 1000 + 100_000).is_err());
+        // 签名不包含当前时间 过期后签名依然有效
+        generator.verify_sign(7, token).unwrap();
+    }
+
+    #[test]
+    fn expire_wraparound() {
+        let generator = generator(3600);
+        let max = (1 << EXPIRE_BITS) - 1;
+        // 过期时间回绕到0附近
+        let now = max - 10;
+        let token = generator.generate_at(7, now, 0);
+        assert_eq!(token >> (SIGN_BITS + NONCE_BITS), 49);
+        generator.verify_expire_at(token, now).unwrap();
+        generator.verify_expire_at(token, max).unwrap();
+        generator
+            .verify_expire_at(token, (max + 1 + 49) & max)
+            .unwrap();
+        assert!(generator
+            .verify_expire_at(token, (max + 1 + 50) & max)
+            .is_err());
+        // 当前分钟数超过20位时取低位比较
+        let token = generator.generate_at(7, (1 << EXPIRE_BITS) * 3 + 5, 0);
+        generator.verify_expire_at(token, 5).unwrap();
+        assert!(generator.verify_expire_at(token, 66).is_err());
+    }
+
+    #[test]
+    fn expire_limit() {
+        assert!(HmacTokenGenerator::new(1, b"secret", 180 * 24 * 3600).is_ok());
+        assert!(HmacTokenGenerator::new(1, b"secret", 400 * 24 * 3600).is_err());
+        assert!(HmacTokenGenerator::new(1, b"", 60).is_err());
+    }
+}
@@ -1,10 +1,13 @@
+use anyhow::{bail, Context, Result};
 use aqueue::RwModel;
 use once_cell::sync::Lazy;
 use std::env::current_dir;
-use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use crate::config::Config;
 use crate::services::{BroadcastService, MasterService, ProxyService};
+use crate::GAME;
 
 /// 当前运行路径
 pub static CURRENT_EXE_PATH: Lazy<String> = Lazy::new(|| match std::env::current_exe() {
@@ -17,60 +20,61 @@ pub static CURRENT_EXE_PATH: Lazy<String> = Lazy::new(|| match std::env::current
     Err(err) => panic!("current_exe_path get error:{err:?}"),
 });
 
-/// 配置
-pub static BASE_CONFIG: Lazy<Config> = Lazy::new(|| {
-    Config::load_config(
-        &load_content("base_config.toml").expect("read base_config.toml file error"),
-    )
-    .expect("toml base_config.toml error")
+/// 全局配置
+/// 以下静态服务为全局 GAME 使用的兼容层,自建 Game 实例无需使用
+/// 已安装 GAME 时为 GAME 的配置,否则加载默认配置,加载失败会panic
+/// 首次访问时的快照,不会随在线重载更新,请使用 Game::config
+pub static BASE_CONFIG: Lazy<Config> = Lazy::new(|| match GAME.get() {
+    Some(game) => game.config(),
+    None => match Config::load_default() {
+        Ok(config) => config,
+        Err(err) => panic!("load base_config.toml error:{err:?}"),
+    },
 });
 
 /// 代理管理器
-pub static PROXY: Lazy<RwModel<ProxyService>> = Lazy::new(|| RwModel::new(ProxyService::default()));
+pub static PROXY: Lazy<Arc<RwModel<ProxyService>>> =
+    Lazy::new(|| Arc::new(RwModel::new(ProxyService::default())));
 
 /// 广播服务
-pub static BROADCAST_SERVICE: Lazy<BroadcastService> = Lazy::new(|| BroadcastService);
+pub static BROADCAST_SERVICE: Lazy<BroadcastService> =
+    Lazy::new(|| BroadcastService::new(PROXY.clone()));
 
 /// MASTER 服务器
-pub static MASTER_SERVICE: Lazy<MasterService> =
-    Lazy::new(|| MasterService::new(BASE_CONFIG.master.clone()));
+/// 已安装 GAME 时为 GAME 的 master 服务
+pub static MASTER_SERVICE: Lazy<Arc<MasterService>> = Lazy::new(|| match GAME.get() {
+    Some(game) => game.master.clone(),
+    None => Arc::new(MasterService::new(BASE_CONFIG.master.clone())),
+});
 
-/// 加载文件内容
+/// 查找文件
+/// 依次查找 exe目录,当前目录,当前目录的上级目录
 #[inline]
-pub fn load_content(filename: &str) -> std::io::Result<String> {
-    let exe_path = format!("{}/{}", CURRENT_EXE_PATH.as_str(), filename);
-    let config_path = Path::new(&exe_path);
-    let path = {
-        if !config_path.exists() {
-            let json_path = format!(
-                "{}/{}",
-                current_dir().expect("not found current dir").display(),
-                filename
-            );
-            let path = Path::new(&json_path);
-            if !path.exists() {
-                let json_path = format!(
-                    "{}/{}",
-                    current_dir()
-                        .expect("not found current dir")
-                        .parent()
-                        .expect("not found config file")
-                        .display(),
-                    filename
-                );
-                let path = Path::new(&json_path);
-                if !path.exists() {
-                    panic!("not found config file:{path:?}");
-                } else {
-                    path.to_path_buf()
-                }
-            } else {
-                path.to_path_buf()
-            }
-        } else {
-            config_path.to_path_buf()
+pub fn find_file(filename: &str) -> Result<PathBuf> {
+    let mut search = Vec::with_capacity(3);
+    if let Ok(exe_path) = std::env::current_exe() {
+        if let Some(exe_dir) = exe_path.parent() {
+            search.push(exe_dir.join(filename));
+        }
+    }
+    let current = current_dir().context("not found current dir")?;
+    search.push(current.join(filename));
+    if let Some(parent) = current.parent() {
+        search.push(parent.join(filename));
+    }
+
+    for path in search.iter() {
+        if path.exists() {
+            return Ok(path.clone());
         }
-    };
+    }
+    bail!("not found file:{filename} in {search:?}")
+}
 
+/// 加载文件内容
+#[inline]
+pub fn load_content(filename: &str) -> std::io::Result<String> {
+    let path = find_file(filename)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::NotFound, err.to_string()))?;
     std::fs::read_to_string(path)
 }
@@ -1,5 +1,7 @@
 use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
 
+pub use crate::clock::now;
+
 /// 1毫秒=10000TICK
 pub const TICK: i64 = 10000;
 
@@ -18,19 +20,19 @@ pub const DAY: i64 = 24 * HOUR;
 /// 获取Utc时间戳 秒后 7个0
 #[inline]
 pub fn timestamp() -> i64 {
-    Utc::now().timestamp_nanos_opt().unwrap() / 100
+    now().timestamp_nanos_opt().unwrap() / 100
 }
 
 /// 获取Utc时间戳 纳秒级
 #[inline]
 pub fn timestamp_nanos() -> u64 {
-    Utc::now().timestamp_nanos_opt().unwrap() as u64
+    now().timestamp_nanos_opt().unwrap() as u64
 }
 
 /// 获取UTC 毫秒时间戳
 #[inline]
 pub fn timestamp_milliseconds() -> i64 {
-    Utc::now().timestamp_nanos_opt().unwrap() / 1_000_000
+    now().timestamp_nanos_opt().unwrap() / 1_000_000
 }
 
 /// 从本地时间获取时间戳
@@ -42,7 +44,7 @@ pub fn timestamp_from_time(time: DateTime<Local>) -> i64 {
 /// 获取本地时间戳
 #[inline]
 pub fn local_timestamp() -> i64 {
-    Local::now().timestamp_nanos_opt().unwrap() / 100
+    now().with_timezone(&Local).timestamp_nanos_opt().unwrap() / 100
 }
 
 /// 从UTC时间获取时间戳
@@ -66,49 +68,49 @@ pub fn timestamp_to_utc_time(utc_timestamp: i64) -> DateTime<Utc> {
 /// 将当前UTC 时间 减天数
 #[inline]
 pub fn get_utc_sub_day(day: i64) -> i64 {
-    (Utc::now().timestamp_millis() - day * DAY) * TICK
+    (now().timestamp_millis() - day * DAY) * TICK
 }
 
 /// 将当前UTC 时间 加天数
 #[inline]
 pub fn get_utc_add_day(day: i64) -> i64 {
-    (Utc::now().timestamp_millis() + day * DAY) * TICK
+    (now().timestamp_millis() + day * DAY) * TICK
 }
 
 /// 将当前UTC 时间 减分钟
 #[inline]
 pub fn get_utc_sub_minute(minute: i64) -> i64 {
-    (Utc::now().timestamp_millis() - minute * MINUTE) * 10000
+    (now().timestamp_millis() - minute * MINUTE) * 10000
 }
 
 /// 将当前UTC 时间 加分钟
 #[inline]
 pub fn get_utc_add_minute(minute: i64) -> i64 {
-    (Utc::now().timestamp_millis() + minute * MINUTE) * TICK
+    (now().timestamp_millis() + minute * MINUTE) * TICK
 }
 
 /// 将当前时间 减天数
 #[inline]
 pub fn get_local_sub_day(day: i64) -> i64 {
-    (Local::now().timestamp_millis() - day * 24 * HOUR) * TICK
+    (now().with_timezone(&Local).timestamp_millis() - day * 24 * HOUR) * TICK
 }
 
 /// 将当前时间 减天数
 #[inline]
 pub fn get_local_add_day(day: i64) -> i64 {
-    (Local::now().timestamp_millis() + day * DAY) * TICK
+    (now().with_timezone(&Local).timestamp_millis() + day * DAY) * TICK
 }
 
 /// 将当前时间 减分钟
 #[inline]
 pub fn get_local_sub_minute(minute: i64) -> i64 {
-    (Local::now().timestamp_millis() - minute * MINUTE) * TICK
+    (now().with_timezone(&Local).timestamp_millis() - minute * MINUTE) * TICK
 }
 
 /// 将当前时间 减分钟
 #[inline]
 pub fn get_local_add_minute(minute: i64) -> i64 {
-    (Local::now().timestamp_millis() + minute * MINUTE) * TICK
+    (now().with_timezone(&Local).timestamp_millis() + minute * MINUTE) * TICK
 }
 
 /// 给UTC时间 + 多少分钟时间 并算出时间戳
@@ -120,7 +122,7 @@ pub fn utc_time_add_minute(time: DateTime<Utc>, minute: i64) -> i64 {
 /// 给naive时间 + 多少分钟时间 并算出时间戳
 #[inline]
 pub fn naive_time_add_minute(time: NaiveDateTime, minute: i64) -> i64 {
-    (time.timestamp_millis() + minute * MINUTE) * TICK
+    (time.and_utc().timestamp_millis() + minute * MINUTE) * TICK
 }
 
 /// 给UTC时间 - 多少分钟时间 并算出时间戳
@@ -162,11 +164,12 @@ pub fn new_uuid() -> String {
 /// 获取local当天0点0分0秒时间戳
 #[inline]
 pub fn get_now_day_timestamp(timezone_minute: i64) -> i64 {
-    let utc_zero = Utc::now()
+    let utc_zero = now()
         .naive_utc()
         .date()
         .and_hms_opt(0, 0, 0)
         .unwrap()
+        .and_utc()
         .timestamp_nanos_opt()
         .unwrap()
         / 100;
@@ -189,10 +192,11 @@ pub fn get_now_sub_day(timezone_minute: i64, day: i64) -> i64 {
 /// 获取UTC 当天0点0分0秒时间戳
 #[inline]
 pub fn get_utc_now_day_timestamp() -> i64 {
-    Utc::now()
+    now()
         .date_naive()
         .and_hms_opt(0, 0, 0)
         .unwrap()
+        .and_utc()
         .timestamp_nanos_opt()
         .unwrap()
         / 100
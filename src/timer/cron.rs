@@ -0,0 +1,380 @@
+use anyhow::{bail, ensure, Context, Result};
+use chrono::{DateTime, Datelike, Duration, FixedOffset, NaiveDate, Timelike, Utc};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use crate::time::MINUTE;
+
+/// 最多向后查找的天数
+const MAX_SEARCH_DAY: i64 = 366 * 5;
+
+/// cron 表达式调度
+/// 格式为 "分 时 日 月 周",支持 * , - / 以及 @hourly @daily @weekly @monthly @yearly
+/// 周 0或7为周日,可用 SUN MON TUE WED THU FRI SAT,月可用 JAN-DEC
+/// 时区为相对UTC的分钟数,和 time::get_now_day_timestamp(timezone_minute) 一致
+/// ``` ignore
+/// // 每天 0点
+/// CronSchedule::parse("0 0 * * *", 480)?;
+/// // 每周一 4点
+/// CronSchedule::parse("0 4 * * MON", 480)?;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    expr: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    day_restricted: bool,
+    weekday_restricted: bool,
+    timezone_minute: i64,
+}
+
+impl CronSchedule {
+    /// 解析 cron 表达式
+    pub fn parse(expr: &str, timezone_minute: i64) -> Result<Self> {
+        ensure!(
+            timezone_minute.abs() < 24 * 60,
+            "cron timezone minute:{timezone_minute} out of range"
+        );
+        let fields = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        }
+        .split_whitespace()
+        .collect::<Vec<_>>();
+        ensure!(
+            fields.len() == 5,
+            "cron:{expr} must have 5 fields: minute hour day month weekday"
+        );
+
+        let weekdays = parse_field(fields[4], 0, 7, WEEKDAY_NAMES)
+            .with_context(|| format!("cron:{expr} weekday error"))?;
+        Ok(Self {
+            expr: expr.to_string(),
+            minutes: parse_field(fields[0], 0, 59, &[])
+                .with_context(|| format!("cron:{expr} minute error"))?,
+            hours: parse_field(fields[1], 0, 23, &[])
+                .with_context(|| format!("cron:{expr} hour error"))?,
+            days: parse_field(fields[2], 1, 31, &[])
+                .with_context(|| format!("cron:{expr} day error"))?,
+            months: parse_field(fields[3], 1, 12, MONTH_NAMES)
+                .with_context(|| format!("cron:{expr} month error"))?,
+            // 7 同为周日
+            weekdays: (weekdays | (weekdays >> 7)) & 0x7f,
+            day_restricted: !fields[2].starts_with('*'),
+            weekday_restricted: !fields[4].starts_with('*'),
+            timezone_minute,
+        })
+    }
+
+    /// 时区 相对UTC的分钟数
+    #[inline]
+    pub fn timezone_minute(&self) -> i64 {
+        self.timezone_minute
+    }
+
+    /// 获取 time 之后的下一次触发时间
+    /// 5年内没有可触发的时间返回None
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let offset = FixedOffset::east_opt((self.timezone_minute * 60) as i32)?;
+        let local = time.with_timezone(&offset).naive_local();
+        let mut next = local.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = next + Duration::days(MAX_SEARCH_DAY);
+
+        while next < limit {
+            if !contains(self.months, next.month()) {
+                next = first_day_of_next_month(next.date())?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !self.match_day(next.date()) {
+                next = next.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+                continue;
+            }
+            if !contains(self.hours, next.hour()) {
+                next = next.with_minute(0)? + Duration::hours(1);
+                continue;
+            }
+            if !contains(self.minutes, next.minute()) {
+                next += Duration::minutes(1);
+                continue;
+            }
+            return Some(next.and_utc() - Duration::milliseconds(self.timezone_minute * MINUTE));
+        }
+        None
+    }
+
+    /// 日和周都限定时满足其一即可
+    #[inline]
+    fn match_day(&self, date: NaiveDate) -> bool {
+        let day = contains(self.days, date.day());
+        let weekday = contains(self.weekdays, date.weekday().num_days_from_sunday());
+        match (self.day_restricted, self.weekday_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        }
+    }
+}
+
+impl Display for CronSchedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (UTC{:+}min)", self.expr, self.timezone_minute)
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = anyhow::Error;
+
+    /// 使用UTC时区解析
+    #[inline]
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s, 0)
+    }
+}
+
+const WEEKDAY_NAMES: &[&str] = &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+const MONTH_NAMES: &[&str] = &[
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+
+#[inline]
+fn contains(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+#[inline]
+fn first_day_of_next_month(date: NaiveDate) -> Option<NaiveDate> {
+    if date.month() == 12 {
+        NaiveDate::from_ymd_opt(date.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(date.year(), date.month() + 1, 1)
+    }
+}
+
+/// 解析单个字段 返回位图
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step = step.parse::<u32>().context("step error")?;
+                ensure!(step > 0, "step must > 0");
+                (range, Some(step))
+            }
+            None => (item, None),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                parse_value(start, min, names)?,
+                parse_value(end, min, names)?,
+            )
+        } else {
+            let start = parse_value(range, min, names)?;
+            // a/n 表示从 a 到最大值
+            (start, if step.is_some() { max } else { start })
+        };
+        if start < min || end > max || start > end {
+            bail!("{item} out of range {min}-{max}");
+        }
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[inline]
+fn parse_value(value: &str, min: u32, names: &[&str]) -> Result<u32> {
+    if let Some(index) = names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+    {
+        return Ok(index as u32 + min);
+    }
+    value
+        .parse::<u32>()
+        .with_context(|| format!("{value} is not number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    fn next(expr: &str, time: DateTime<Utc>) -> DateTime<Utc> {
+        expr.parse::<CronSchedule>()
+            .unwrap()
+            .next_after(time)
+            .unwrap()
+    }
+
+    #[test]
+    fn next_is_strictly_after() {
+        assert_eq!(
+            next("0 * * * *", utc(2024, 1, 1, 10, 0)),
+            utc(2024, 1, 1, 11, 0)
+        );
+        let time = utc(2024, 1, 1, 10, 0) + Duration::seconds(30);
+        assert_eq!(next("* * * * *", time), utc(2024, 1, 1, 10, 1));
+    }
+
+    #[test]
+    fn ranges_and_lists() {
+        let expr = "0 9-11 * * *";
+        assert_eq!(next(expr, utc(2024, 1, 1, 8, 30)), utc(2024, 1, 1, 9, 0));
+        assert_eq!(next(expr, utc(2024, 1, 1, 11, 0)), utc(2024, 1, 2, 9, 0));
+        let expr = "10,40 3,15 * * *";
+        assert_eq!(next(expr, utc(2024, 1, 1, 3, 10)), utc(2024, 1, 1, 3, 40));
+        assert_eq!(next(expr, utc(2024, 1, 1, 3, 40)), utc(2024, 1, 1, 15, 10));
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(
+            next("*/15 * * * *", utc(2024, 1, 1, 10, 7)),
+            utc(2024, 1, 1, 10, 15)
+        );
+        // a/n 从a开始到最大值
+        let expr = "5/20 * * * *";
+        assert_eq!(next(expr, utc(2024, 1, 1, 10, 5)), utc(2024, 1, 1, 10, 25));
+        assert_eq!(next(expr, utc(2024, 1, 1, 10, 46)), utc(2024, 1, 1, 11, 5));
+        assert_eq!(
+            next("0 0-12/6 * * *", utc(2024, 1, 1, 6, 0)),
+            utc(2024, 1, 1, 12, 0)
+        );
+        assert_eq!(
+            next("0 0-12/6 * * *", utc(2024, 1, 1, 12, 0)),
+            utc(2024, 1, 2, 0, 0)
+        );
+    }
+
+    #[test]
+    fn names_and_macros() {
+        // 2024-01-01 是周一
+        assert_eq!(
+            next("0 4 * * MON", utc(2024, 1, 1, 5, 0)),
+            utc(2024, 1, 8, 4, 0)
+        );
+        assert_eq!(
+            next("0 4 * * mon-wed", utc(2024, 1, 1, 5, 0)),
+            utc(2024, 1, 2, 4, 0)
+        );
+        assert_eq!(
+            next("0 0 1 FEB *", utc(2024, 1, 15, 0, 0)),
+            utc(2024, 2, 1, 0, 0)
+        );
+        assert_eq!(
+            next("@hourly", utc(2024, 1, 1, 10, 30)),
+            utc(2024, 1, 1, 11, 0)
+        );
+        assert_eq!(
+            next("@weekly", utc(2024, 1, 1, 0, 0)),
+            utc(2024, 1, 7, 0, 0)
+        );
+        assert_eq!(
+            next("@yearly", utc(2024, 6, 1, 0, 0)),
+            utc(2025, 1, 1, 0, 0)
+        );
+    }
+
+    #[test]
+    fn weekday_seven_is_sunday() {
+        let time = utc(2024, 1, 1, 0, 0);
+        assert_eq!(next("0 0 * * 7", time), utc(2024, 1, 7, 0, 0));
+        assert_eq!(next("0 0 * * 7", time), next("0 0 * * 0", time));
+        assert_eq!(next("0 0 * * 6-7", time), utc(2024, 1, 6, 0, 0));
+    }
+
+    #[test]
+    fn day_or_weekday() {
+        // 日和周都限定时满足其一即可
+        let expr = "0 0 13 * FRI";
+        assert_eq!(next(expr, utc(2024, 1, 1, 0, 0)), utc(2024, 1, 5, 0, 0));
+        assert_eq!(next(expr, utc(2024, 1, 12, 0, 0)), utc(2024, 1, 13, 0, 0));
+        // 只限定日
+        assert_eq!(
+            next("0 0 13 * *", utc(2024, 1, 1, 0, 0)),
+            utc(2024, 1, 13, 0, 0)
+        );
+        // 日为 * 时只看周
+        assert_eq!(
+            next("0 0 * * FRI", utc(2024, 1, 5, 0, 0)),
+            utc(2024, 1, 12, 0, 0)
+        );
+    }
+
+    #[test]
+    fn month_and_year_rollover() {
+        // 2月没有31日
+        assert_eq!(
+            next("0 0 31 * *", utc(2024, 1, 31, 1, 0)),
+            utc(2024, 3, 31, 0, 0)
+        );
+        assert_eq!(
+            next("30 23 31 12 *", utc(2024, 12, 31, 23, 30)),
+            utc(2025, 12, 31, 23, 30)
+        );
+        assert_eq!(
+            next("0 0 29 2 *", utc(2024, 3, 1, 0, 0)),
+            utc(2028, 2, 29, 0, 0)
+        );
+        assert!("0 0 30 2 *"
+            .parse::<CronSchedule>()
+            .unwrap()
+            .next_after(utc(2024, 1, 1, 0, 0))
+            .is_none());
+    }
+
+    #[test]
+    fn timezone_offset() {
+        let time = utc(2024, 1, 1, 0, 0);
+        // UTC+8 的0点是UTC前一天16点
+        let schedule = CronSchedule::parse("0 0 * * *", 480).unwrap();
+        assert_eq!(schedule.next_after(time), Some(utc(2024, 1, 1, 16, 0)));
+        // UTC-5 的0点是UTC 5点
+        let schedule = CronSchedule::parse("0 0 * * *", -300).unwrap();
+        assert_eq!(schedule.next_after(time), Some(utc(2024, 1, 1, 5, 0)));
+        // 按本地日期判断周 UTC+8 2024-01-01 16:00 起是周二
+        let schedule = CronSchedule::parse("0 1 * * TUE", 480).unwrap();
+        assert_eq!(schedule.next_after(time), Some(utc(2024, 1, 1, 17, 0)));
+        assert_eq!(schedule.timezone_minute(), 480);
+    }
+
+    #[test]
+    fn invalid() {
+        for expr in [
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * 32 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "abc * * * *",
+            "1,,2 * * * *",
+            "* * * FOO *",
+            "@every",
+        ] {
+            assert!(expr.parse::<CronSchedule>().is_err(), "{expr}");
+        }
+        assert!(CronSchedule::parse("* * * * *", 24 * 60).is_err());
+        assert!(CronSchedule::parse("* * * * *", -24 * 60).is_err());
+    }
+}
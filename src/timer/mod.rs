@@ -0,0 +1,848 @@
+mod cron;
+mod options;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use log::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use tokio::sync::{watch, Notify};
+use tokio::time::{timeout, Duration, Instant};
+
+use crate::clock;
+use crate::services::{ILinkPeerManager, MasterService};
+
+pub use cron::CronSchedule;
+pub use options::*;
+
+/// 定时器调度方式
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// 固定间隔(毫秒) now_run为true时启动后立即运行一次
+    Interval { now_run: bool, interval_ms: u64 },
+    /// cron 表达式
+    Cron(CronSchedule),
+}
+
+/// 计算下次运行时间
+struct Planner {
+    schedule: Schedule,
+    mode: ScheduleMode,
+    /// 上次按计划运行的时间点
+    last: Option<DateTime<Utc>>,
+}
+
+impl Planner {
+    /// 下次运行时间
+    fn next(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match self.schedule {
+            Schedule::Interval { interval_ms, .. } => {
+                let interval = interval_ms.max(1) as i64;
+                match (self.mode, self.last) {
+                    (ScheduleMode::FixedRate, Some(last)) => {
+                        let next = last + chrono::Duration::milliseconds(interval);
+                        if next >= now {
+                            return Some(next);
+                        }
+                        // 跳过错过的次数
+                        let missed = (now - next).num_milliseconds() / interval + 1;
+                        Some(next + chrono::Duration::milliseconds(missed * interval))
+                    }
+                    _ => Some(now + chrono::Duration::milliseconds(interval_ms as i64)),
+                }
+            }
+            // 从上次时间点之后计算,避免同一时间点重复运行
+            Schedule::Cron(ref cron) => {
+                cron.next_after(self.last.map_or(now, |last| now.max(last)))
+            }
+        }
+    }
+
+    /// 本次运行是否超时 超过间隔或错过下一个时间点
+    fn is_overrun(&self, start: DateTime<Utc>, elapsed: Duration) -> bool {
+        match self.schedule {
+            Schedule::Interval { interval_ms, .. } => elapsed.as_millis() > interval_ms as u128,
+            Schedule::Cron(ref cron) => cron
+                .next_after(start)
+                .is_some_and(|next| clock::now() > next),
+        }
+    }
+}
+
+/// 定时器
+#[async_trait::async_trait]
+pub trait Timer: Send + Sync {
+    /// 初始化,并返回间隔时间
+    /// 只实现 schedule 时可不实现
+    async fn init(&self) -> Result<(bool, u64)> {
+        bail!("timer not implemented init or schedule")
+    }
+    /// 初始化,并返回调度方式
+    /// 默认使用 init 返回的间隔时间
+    async fn schedule(&self) -> Result<Schedule> {
+        let (now_run, interval_ms) = self.init().await?;
+        Ok(Schedule::Interval {
+            now_run,
+            interval_ms,
+        })
+    }
+    /// 运行
+    async fn run(&self) -> Result<()>;
+    /// 名称 用于日志和统计
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+    /// 运行选项
+    fn options(&self) -> TimerOptions {
+        TimerOptions::default()
+    }
+}
+
+/// 定时器状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimerState {
+    /// 运行中
+    Running,
+    /// 已暂停
+    Paused,
+    /// 已取消
+    Cancelled,
+    /// 出错或没有下次运行时间而停止
+    Stopped,
+}
+
+/// 定时器控制
+struct TimerControl {
+    state: watch::Sender<TimerState>,
+    run_now: Notify,
+    stats: Mutex<TimerStats>,
+}
+
+/// 等待结果
+enum Wake {
+    /// 到时间
+    Tick,
+    /// 要求立即运行
+    RunNow,
+    /// 恢复运行,需要重新计算下次运行时间
+    Reschedule,
+    /// 已取消
+    Cancelled,
+}
+
+impl TimerControl {
+    /// 等待 delay 后运行
+    /// 暂停期间只响应立即运行,恢复后重新计时
+    async fn wait(&self, state: &mut watch::Receiver<TimerState>, delay: Duration) -> Wake {
+        let sleep = clock::sleep(delay);
+        tokio::pin!(sleep);
+        let mut paused = false;
+        loop {
+            match *state.borrow_and_update() {
+                TimerState::Cancelled | TimerState::Stopped => return Wake::Cancelled,
+                TimerState::Paused => paused = true,
+                TimerState::Running if paused => return Wake::Reschedule,
+                TimerState::Running => {}
+            }
+            // 先检查状态 同时到时间和取消时不再运行
+            tokio::select! {
+                biased;
+                changed = state.changed() => {
+                    if changed.is_err() {
+                        return Wake::Cancelled;
+                    }
+                }
+                _ = self.run_now.notified() => return Wake::RunNow,
+                _ = &mut sleep, if !paused => return Wake::Tick,
+            }
+        }
+    }
+}
+
+/// 定时器句柄
+/// 可取消,暂停,恢复和立即运行
+#[derive(Clone)]
+pub struct TimerHandle {
+    id: u64,
+    control: Arc<TimerControl>,
+}
+
+impl TimerHandle {
+    /// 定时器id
+    #[inline]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// 当前状态
+    #[inline]
+    pub fn state(&self) -> TimerState {
+        *self.control.state.borrow()
+    }
+
+    /// 运行统计
+    #[inline]
+    pub fn stats(&self) -> TimerStats {
+        self.control
+            .stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// 取消定时器,正在运行的 run 会执行完
+    #[inline]
+    pub fn cancel(&self) {
+        self.set_state(TimerState::Cancelled);
+    }
+
+    /// 暂停定时器
+    #[inline]
+    pub fn pause(&self) {
+        self.set_state(TimerState::Paused);
+    }
+
+    /// 恢复定时器,重新计算下次运行时间
+    #[inline]
+    pub fn resume(&self) {
+        self.set_state(TimerState::Running);
+    }
+
+    /// 立即运行一次,暂停时也会运行
+    #[inline]
+    pub fn run_now(&self) {
+        if !matches!(self.state(), TimerState::Cancelled | TimerState::Stopped) {
+            self.control.run_now.notify_one();
+        }
+    }
+
+    /// 已取消或停止后不再变更
+    #[inline]
+    fn set_state(&self, state: TimerState) {
+        self.control.state.send_if_modified(|current| {
+            if matches!(*current, TimerState::Cancelled | TimerState::Stopped) || *current == state
+            {
+                false
+            } else {
+                *current = state;
+                true
+            }
+        });
+    }
+}
+
+type TimerMap = Arc<Mutex<HashMap<u64, TimerHandle>>>;
+
+/// 定时器管理器
+/// start 前添加的定时器在 start 时运行,之后添加的立即运行
+#[derive(Default)]
+pub struct TimerManager {
+    started: AtomicBool,
+    next_id: AtomicU64,
+    timers: TimerMap,
+    pending: Mutex<Vec<(TimerHandle, Box<dyn Timer>)>>,
+}
+
+impl TimerManager {
+    pub fn new(timers: Vec<Box<dyn Timer>>) -> Self {
+        let manager = Self::default();
+        for timer in timers {
+            manager.add_boxed(timer);
+        }
+        manager
+    }
+
+    /// 添加定时器
+    #[inline]
+    pub fn add(&self, timer: impl Timer + 'static) -> TimerHandle {
+        self.add_boxed(Box::new(timer))
+    }
+
+    /// 添加定时器
+    pub fn add_boxed(&self, timer: Box<dyn Timer>) -> TimerHandle {
+        let (state, _) = watch::channel(TimerState::Running);
+        let handle = TimerHandle {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            control: Arc::new(TimerControl {
+                state,
+                run_now: Notify::new(),
+                stats: Mutex::new(TimerStats {
+                    name: timer.name().to_string(),
+                    ..Default::default()
+                }),
+            }),
+        };
+        self.timers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(handle.id, handle.clone());
+
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.started.load(Ordering::Acquire) {
+            drop(pending);
+            spawn_timer(self.timers.clone(), handle.clone(), timer);
+        } else {
+            pending.push((handle.clone(), timer));
+        }
+        handle
+    }
+
+    /// 获取定时器句柄
+    #[inline]
+    pub fn get(&self, id: u64) -> Option<TimerHandle> {
+        self.timers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&id)
+            .cloned()
+    }
+
+    /// 所有未结束定时器的运行统计 按id排序
+    pub fn stats(&self) -> Vec<(u64, TimerStats)> {
+        let mut stats = self
+            .timers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .map(|handle| (handle.id, handle.stats()))
+            .collect::<Vec<_>>();
+        stats.sort_unstable_by_key(|(id, _)| *id);
+        stats
+    }
+
+    /// 未结束的定时器数量
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.timers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// 是否没有定时器
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 启动定时器
+    pub fn start(&self) {
+        let mut pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        if self
+            .started
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            for (handle, timer) in pending.drain(..) {
+                spawn_timer(self.timers.clone(), handle, timer);
+            }
+            info!("timer is start");
+        }
+    }
+
+    /// 取消所有定时器
+    pub fn cancel_all(&self) {
+        let handles = self
+            .timers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .drain()
+            .map(|(_, handle)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.cancel();
+        }
+        self.pending
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
+
+/// 运行定时器,结束后从管理器移除
+fn spawn_timer(timers: TimerMap, handle: TimerHandle, timer: Box<dyn Timer>) {
+    tokio::spawn(async move {
+        run_timer(&handle, timer.as_ref()).await;
+        handle.set_state(TimerState::Stopped);
+        timers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&handle.id);
+    });
+}
+
+async fn run_timer(handle: &TimerHandle, timer: &dyn Timer) {
+    let control = &handle.control;
+    let name = timer.name();
+    let options = timer.options();
+    let mut state = control.state.subscribe();
+    let schedule = match timer.schedule().await {
+        Ok(schedule) => schedule,
+        Err(err) => {
+            error!("timer:{name} init error:{:?}", err);
+            return;
+        }
+    };
+    let mut now_run = matches!(schedule, Schedule::Interval { now_run: true, .. });
+    let mut planner = Planner {
+        schedule,
+        mode: options.mode,
+        last: None,
+    };
+    // 已重试次数
+    let mut retry = 0;
+    // 出错后的重试等待时间
+    let mut retry_delay = None;
+
+    loop {
+        if matches!(*state.borrow(), TimerState::Cancelled | TimerState::Stopped) {
+            break;
+        }
+        if !now_run {
+            let now = clock::now();
+            let (delay, next) = match retry_delay.take() {
+                Some(delay_ms) => (Duration::from_millis(delay_ms), None),
+                None => {
+                    let Some(next) = planner.next(now) else {
+                        warn!("timer:{name} has no next time, timer stop");
+                        break;
+                    };
+                    ((next - now).to_std().unwrap_or_default(), Some(next))
+                }
+            };
+            match control.wait(&mut state, delay).await {
+                Wake::Tick => {
+                    if next.is_some() {
+                        planner.last = next;
+                    }
+                }
+                Wake::RunNow => {}
+                Wake::Reschedule => continue,
+                Wake::Cancelled => break,
+            }
+        }
+        now_run = false;
+
+        let start_time = clock::now();
+        let start = Instant::now();
+        let result = match options.run_timeout {
+            Some(run_timeout) => match timeout(run_timeout, timer.run()).await {
+                Ok(result) => result.map_err(|err| (err, false)),
+                Err(_) => Err((anyhow!("run timeout {:?}", run_timeout), true)),
+            },
+            None => timer.run().await.map_err(|err| (err, false)),
+        };
+        let elapsed = start.elapsed();
+        let overrun = planner.is_overrun(start_time, elapsed);
+        if overrun {
+            warn!("timer:{name} overrun, elapsed:{:?}", elapsed);
+        }
+
+        let mut stats = control.stats.lock().unwrap_or_else(PoisonError::into_inner);
+        let elapsed_ms = elapsed.as_millis() as u64;
+        stats.runs += 1;
+        stats.last_duration_ms = elapsed_ms;
+        stats.max_duration_ms = stats.max_duration_ms.max(elapsed_ms);
+        stats.total_duration_ms += elapsed_ms;
+        stats.last_run_time = Some(start_time.timestamp_millis());
+        stats.overruns += overrun as u64;
+        match result {
+            Ok(()) => {
+                stats.consecutive_errors = 0;
+                retry = 0;
+            }
+            Err((err, is_timeout)) => {
+                error!("timer:{name} error:{:?}", err);
+                stats.errors += 1;
+                stats.timeouts += is_timeout as u64;
+                stats.consecutive_errors += 1;
+                stats.last_error = Some(format!("{err:?}"));
+                stats.last_error_time = Some(clock::now().timestamp_millis());
+                match options.error_policy {
+                    ErrorPolicy::Stop => {
+                        warn!("timer:{name} stop by error");
+                        break;
+                    }
+                    ErrorPolicy::Retry { .. } => {
+                        // 超过重试次数后等待下次调度
+                        retry_delay = options.error_policy.retry_delay_ms(retry);
+                        retry = if retry_delay.is_some() { retry + 1 } else { 0 };
+                    }
+                    ErrorPolicy::Continue => {}
+                }
+            }
+        }
+    }
+}
+
+/// 定时清理peer
+/// 间隔为清理超时时间的十分之一,最少1秒
+pub struct PeerCleanTimer {
+    peers: Arc<dyn ILinkPeerManager>,
+    interval_sec: u64,
+}
+
+impl PeerCleanTimer {
+    pub fn new(peers: Arc<dyn ILinkPeerManager>, clean_timeout_sec: i64) -> Self {
+        Self {
+            peers,
+            interval_sec: (clean_timeout_sec / 10).max(1) as u64,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Timer for PeerCleanTimer {
+    #[inline]
+    async fn init(&self) -> Result<(bool, u64)> {
+        Ok((false, self.interval_sec * 1000))
+    }
+
+    #[inline]
+    async fn run(&self) -> Result<()> {
+        self.peers.cleans().await
+    }
+}
+
+/// 定时通知master 还存在peer的账号保活
+pub struct AccountAliveTimer {
+    peers: Arc<dyn ILinkPeerManager>,
+    master: Arc<MasterService>,
+    interval_sec: u64,
+}
+
+impl AccountAliveTimer {
+    pub fn new(
+        peers: Arc<dyn ILinkPeerManager>,
+        master: Arc<MasterService>,
+        interval_sec: u64,
+    ) -> Self {
+        Self {
+            peers,
+            master,
+            interval_sec,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Timer for AccountAliveTimer {
+    #[inline]
+    async fn init(&self) -> Result<(bool, u64)> {
+        Ok((false, self.interval_sec * 1000))
+    }
+
+    #[inline]
+    async fn run(&self) -> Result<()> {
+        let account_ids = self.peers.get_account_ids().await;
+        if !account_ids.is_empty() {
+            self.master.alive_account(&account_ids).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use chrono::TimeZone;
+
+    fn utc(h: u32, min: u32, sec: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, h, min, sec).unwrap()
+    }
+
+    fn ms(ms: i64) -> chrono::Duration {
+        chrono::Duration::milliseconds(ms)
+    }
+
+    fn interval(mode: ScheduleMode, last: Option<DateTime<Utc>>) -> Planner {
+        Planner {
+            schedule: Schedule::Interval {
+                now_run: false,
+                interval_ms: 1000,
+            },
+            mode,
+            last,
+        }
+    }
+
+    #[test]
+    fn fixed_delay_next() {
+        let now = utc(10, 0, 0);
+        assert_eq!(
+            interval(ScheduleMode::FixedDelay, None).next(now),
+            Some(now + ms(1000))
+        );
+        let last = Some(now - ms(5000));
+        assert_eq!(
+            interval(ScheduleMode::FixedDelay, last).next(now),
+            Some(now + ms(1000))
+        );
+    }
+
+    #[test]
+    fn fixed_rate_skips_missed() {
+        let last = utc(10, 0, 0);
+        let planner = interval(ScheduleMode::FixedRate, Some(last));
+        assert_eq!(planner.next(last + ms(300)), Some(last + ms(1000)));
+        assert_eq!(planner.next(last + ms(1000)), Some(last + ms(1000)));
+        // 错过 1000 2000 3000 三个时间点
+        assert_eq!(planner.next(last + ms(3500)), Some(last + ms(4000)));
+        // 还没运行过时从当前时间开始
+        let planner = interval(ScheduleMode::FixedRate, None);
+        assert_eq!(planner.next(last), Some(last + ms(1000)));
+    }
+
+    #[test]
+    fn cron_next_not_repeat() {
+        let planner = Planner {
+            schedule: Schedule::Cron("0 * * * *".parse().unwrap()),
+            mode: ScheduleMode::FixedRate,
+            last: Some(utc(10, 0, 0)),
+        };
+        // 时钟稍慢时不会在同一时间点再次运行
+        assert_eq!(planner.next(utc(9, 59, 59)), Some(utc(11, 0, 0)));
+        assert_eq!(planner.next(utc(10, 30, 0)), Some(utc(11, 0, 0)));
+    }
+
+    #[tokio::test]
+    async fn overrun() {
+        let planner = interval(ScheduleMode::FixedDelay, None);
+        assert!(!planner.is_overrun(utc(10, 0, 0), Duration::from_millis(1000)));
+        assert!(planner.is_overrun(utc(10, 0, 0), Duration::from_millis(1001)));
+
+        let clock = TestClock::set().await;
+        let planner = Planner {
+            schedule: Schedule::Cron("* * * * *".parse().unwrap()),
+            mode: ScheduleMode::FixedRate,
+            last: None,
+        };
+        let start = clock::now();
+        assert!(!planner.is_overrun(start, Duration::ZERO));
+        clock.advance(Duration::from_secs(61));
+        assert!(planner.is_overrun(start, Duration::ZERO));
+    }
+
+    #[test]
+    fn retry_delay() {
+        let policy = ErrorPolicy::Retry {
+            max_retries: 4,
+            initial_ms: 100,
+            max_ms: 500,
+        };
+        let delays = (0..5)
+            .map(|attempt| policy.retry_delay_ms(attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [Some(100), Some(200), Some(400), Some(500), None]);
+        assert_eq!(ErrorPolicy::Stop.retry_delay_ms(0), None);
+        assert_eq!(ErrorPolicy::Continue.retry_delay_ms(0), None);
+    }
+
+    /// 记录运行次数 前 fails 次运行返回错误
+    struct TestTimer {
+        runs: watch::Sender<u64>,
+        fails: u64,
+        run_ms: u64,
+        options: TimerOptions,
+    }
+
+    impl TestTimer {
+        fn new(fails: u64, options: TimerOptions) -> (Self, watch::Receiver<u64>) {
+            let (runs, rx) = watch::channel(0);
+            let timer = Self {
+                runs,
+                fails,
+                run_ms: 0,
+                options,
+            };
+            (timer, rx)
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl Timer for TestTimer {
+        async fn init(&self) -> Result<(bool, u64)> {
+            Ok((false, 1000))
+        }
+
+        async fn run(&self) -> Result<()> {
+            let mut runs = 0;
+            self.runs.send_modify(|count| {
+                *count += 1;
+                runs = *count;
+            });
+            tokio::time::sleep(Duration::from_millis(self.run_ms)).await;
+            if runs <= self.fails {
+                bail!("run:{runs} fail");
+            }
+            Ok(())
+        }
+
+        fn options(&self) -> TimerOptions {
+            self.options.clone()
+        }
+    }
+
+    /// 等待运行次数达到 runs
+    async fn wait_runs(rx: &mut watch::Receiver<u64>, runs: u64) {
+        timeout(Duration::from_secs(5), rx.wait_for(|count| *count >= runs))
+            .await
+            .expect("wait timer run timeout")
+            .unwrap();
+    }
+
+    /// 每次时钟前进 step 后让出,直到运行次数达到 runs
+    async fn advance_until(clock: &TestClock, rx: &mut watch::Receiver<u64>, step: u64, runs: u64) {
+        for _ in 0..1000 {
+            if *rx.borrow() >= runs {
+                return;
+            }
+            clock.advance(Duration::from_millis(step));
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        panic!("timer not run {runs} times");
+    }
+
+    /// 让定时器任务运行
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn run_now_pause_resume_cancel() {
+        let clock = TestClock::set().await;
+        let timers = TimerManager::default();
+        let (timer, mut rx) = TestTimer::new(0, TimerOptions::default());
+        let handle = timers.add(timer);
+        timers.start();
+
+        handle.run_now();
+        wait_runs(&mut rx, 1).await;
+
+        handle.pause();
+        assert_eq!(handle.state(), TimerState::Paused);
+        for _ in 0..5 {
+            clock.advance(Duration::from_secs(2));
+            settle().await;
+        }
+        assert_eq!(*rx.borrow(), 1);
+        // 暂停时也可立即运行
+        handle.run_now();
+        wait_runs(&mut rx, 2).await;
+
+        handle.resume();
+        assert_eq!(handle.state(), TimerState::Running);
+        advance_until(&clock, &mut rx, 500, 3).await;
+
+        handle.cancel();
+        assert_eq!(handle.state(), TimerState::Cancelled);
+        handle.resume();
+        assert_eq!(handle.state(), TimerState::Cancelled);
+        handle.run_now();
+        let runs = *rx.borrow();
+        for _ in 0..5 {
+            clock.advance(Duration::from_secs(2));
+            settle().await;
+        }
+        assert_eq!(*rx.borrow(), runs);
+        assert!(timers.is_empty());
+        assert_eq!(handle.stats().runs, runs);
+    }
+
+    #[tokio::test]
+    async fn error_policy_stop() {
+        let _clock = TestClock::set().await;
+        let timers = TimerManager::default();
+        let (timer, mut rx) = TestTimer::new(
+            u64::MAX,
+            TimerOptions {
+                error_policy: ErrorPolicy::Stop,
+                ..Default::default()
+            },
+        );
+        let handle = timers.add(timer);
+        timers.start();
+        handle.run_now();
+        wait_runs(&mut rx, 1).await;
+        settle().await;
+
+        assert_eq!(handle.state(), TimerState::Stopped);
+        assert!(timers.is_empty());
+        let stats = handle.stats();
+        assert_eq!((stats.runs, stats.errors), (1, 1));
+        assert!(stats.last_error.unwrap().starts_with("run:1 fail"));
+    }
+
+    #[tokio::test]
+    async fn error_policy_retry() {
+        let clock = TestClock::set().await;
+        let timers = TimerManager::default();
+        let (timer, mut rx) = TestTimer::new(
+            2,
+            TimerOptions {
+                error_policy: ErrorPolicy::Retry {
+                    max_retries: 3,
+                    initial_ms: 10,
+                    max_ms: 100,
+                },
+                ..Default::default()
+            },
+        );
+        let handle = timers.add(timer);
+        timers.start();
+        handle.run_now();
+        wait_runs(&mut rx, 1).await;
+
+        // 重试间隔 10ms 20ms 远小于调度间隔 1000ms
+        advance_until(&clock, &mut rx, 10, 3).await;
+        settle().await;
+        let stats = handle.stats();
+        assert_eq!(
+            (stats.runs, stats.errors, stats.consecutive_errors),
+            (3, 2, 0)
+        );
+
+        // 成功后回到正常调度
+        for _ in 0..10 {
+            clock.advance(Duration::from_millis(50));
+            settle().await;
+        }
+        assert_eq!(*rx.borrow(), 3);
+        advance_until(&clock, &mut rx, 100, 4).await;
+        assert_eq!(handle.state(), TimerState::Running);
+        handle.cancel();
+    }
+
+    #[tokio::test]
+    async fn run_timeout() {
+        let _clock = TestClock::set().await;
+        let timers = TimerManager::default();
+        let (mut timer, mut rx) = TestTimer::new(
+            0,
+            TimerOptions {
+                run_timeout: Some(Duration::from_millis(20)),
+                ..Default::default()
+            },
+        );
+        timer.run_ms = 1100;
+        let handle = timers.add(timer);
+        timers.start();
+        handle.run_now();
+        wait_runs(&mut rx, 1).await;
+        timeout(Duration::from_secs(5), async {
+            while handle.stats().runs == 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let stats = handle.stats();
+        assert_eq!((stats.errors, stats.timeouts, stats.overruns), (1, 1, 0));
+        handle.cancel();
+    }
+}
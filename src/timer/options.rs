@@ -0,0 +1,92 @@
+use serde::Serialize;
+use std::time::Duration;
+
+/// 定时器运行出错的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// 停止定时器
+    Stop,
+    /// 按指数退避重试,超过次数后等待下次调度
+    Retry {
+        /// 最多重试次数
+        max_retries: u32,
+        /// 首次重试等待时间(毫秒)
+        initial_ms: u64,
+        /// 最长重试等待时间(毫秒)
+        max_ms: u64,
+    },
+    /// 记录错误,继续按计划运行
+    #[default]
+    Continue,
+}
+
+impl ErrorPolicy {
+    /// 第 attempt 次重试的等待时间(毫秒) 从0开始
+    /// 不需要重试返回None
+    #[inline]
+    pub fn retry_delay_ms(&self, attempt: u32) -> Option<u64> {
+        match *self {
+            ErrorPolicy::Retry {
+                max_retries,
+                initial_ms,
+                max_ms,
+            } if attempt < max_retries => Some(
+                initial_ms
+                    .saturating_mul(1u64 << attempt.min(32))
+                    .min(max_ms),
+            ),
+            _ => None,
+        }
+    }
+}
+
+/// 固定间隔定时器的调度方式
+/// cron 定时器总是按时间点运行
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScheduleMode {
+    /// 上次运行结束后等待间隔时间
+    #[default]
+    FixedDelay,
+    /// 按固定频率运行,运行超时错过的次数跳过
+    FixedRate,
+}
+
+/// 定时器运行选项
+#[derive(Debug, Clone, Default)]
+pub struct TimerOptions {
+    /// 出错处理方式
+    pub error_policy: ErrorPolicy,
+    /// 调度方式
+    pub mode: ScheduleMode,
+    /// 单次运行超时,超时视为出错
+    pub run_timeout: Option<Duration>,
+}
+
+/// 定时器运行统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimerStats {
+    /// 定时器名称
+    pub name: String,
+    /// 运行次数
+    pub runs: u64,
+    /// 出错次数 包括超时
+    pub errors: u64,
+    /// 超时次数
+    pub timeouts: u64,
+    /// 运行时间超过间隔,或错过下一个时间点的次数
+    pub overruns: u64,
+    /// 连续出错次数
+    pub consecutive_errors: u32,
+    /// 上次运行耗时(毫秒)
+    pub last_duration_ms: u64,
+    /// 最长运行耗时(毫秒)
+    pub max_duration_ms: u64,
+    /// 总运行耗时(毫秒)
+    pub total_duration_ms: u64,
+    /// 上次运行开始时间 毫秒时间戳
+    pub last_run_time: Option<i64>,
+    /// 上次错误
+    pub last_error: Option<String>,
+    /// 上次错误时间 毫秒时间戳
+    pub last_error_time: Option<i64>,
+}